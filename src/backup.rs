@@ -1,10 +1,632 @@
-use std::{fs::File, path::Path};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
 
-use flate2::read::GzDecoder;
-use log::debug;
-use tar::Archive;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use humansize::DECIMAL;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder};
+use zip::ZipArchive;
 
-use crate::error::Result;
+use crate::{config::BackupCompression, error::Result, worlds};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// End of central directory record, which is all an empty zip file consists of
+const EMPTY_ZIP_MAGIC: &[u8] = b"PK\x05\x06";
+/// Magic of POSIX and GNU tar headers, located at this offset of the first header
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Archive format of a backup, detected from the first bytes of the file regardless of its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFormat {
+    TarGz,
+    TarZst,
+    Tar,
+    Zip,
+}
+
+impl BackupFormat {
+    pub fn detect(backup_path: &Path) -> Result<Self> {
+        let mut header = vec![];
+        File::open(backup_path)?
+            .take((TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64)
+            .read_to_end(&mut header)?;
+
+        if header.starts_with(GZIP_MAGIC) {
+            Ok(BackupFormat::TarGz)
+        } else if header.starts_with(ZSTD_MAGIC) {
+            Ok(BackupFormat::TarZst)
+        } else if header.starts_with(ZIP_MAGIC) || header.starts_with(EMPTY_ZIP_MAGIC) {
+            Ok(BackupFormat::Zip)
+        } else if header.get(TAR_MAGIC_OFFSET..) == Some(TAR_MAGIC) {
+            Ok(BackupFormat::Tar)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unknown backup format, expected tar.gz, tar.zst, tar or zip archive",
+            )
+            .into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimpleDirEntry {
+    pub name: String,
+    /// Creation time, or modification time on filesystems not recording it
+    pub creation_time: NaiveDateTime,
+    pub size: u64,
+    pub hr_size: String,
+}
+
+/// Order of backups on backups page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupSort {
+    Name,
+    #[default]
+    Date,
+    Size,
+}
+
+/// Sorts backups by given key, ties are ordered by name
+pub fn sort_backups(backups: &mut [SimpleDirEntry], sort: BackupSort, descending: bool) {
+    backups.sort_unstable_by(|a, b| {
+        let ordering = match sort {
+            BackupSort::Name => a.name.cmp(&b.name),
+            BackupSort::Date => a.creation_time.cmp(&b.creation_time),
+            BackupSort::Size => a.size.cmp(&b.size),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// File stored in a backup archive
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupEntry {
+    /// Path relative to the restore destination
+    pub path: String,
+    pub size: u64,
+    pub hr_size: String,
+    pub modification_time: Option<NaiveDateTime>,
+}
+
+/// Index of backup archive, read from tar headers without extracting any file
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupContents {
+    pub entries: Vec<BackupEntry>,
+    /// Names of worlds having any file in the backup
+    pub worlds: Vec<String>,
+    /// Total size of files once extracted
+    pub total_size: u64,
+    pub hr_total_size: String,
+}
+
+/// File written (or to be written by a dry run) by a selective restore
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoredEntry {
+    pub path: String,
+    /// Whether the file already existed in the destination
+    pub overwritten: bool,
+}
+
+/// Backup name as used in URLs and persistent state, i.e. its file name
+pub fn backup_name(backup_path: &Path) -> String {
+    backup_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Lists files in backups directory, oldest first. Hidden files (e.g. uploads in progress) are left out,
+/// entries that cannot be read are skipped with a warning.
+pub fn list_backups(backups_path: &Path) -> Result<Vec<SimpleDirEntry>> {
+    let mut backup_files = vec![];
+    for entry in fs::read_dir(backups_path)? {
+        let (name, metadata) = match entry.and_then(|entry| {
+            Ok((
+                entry.file_name().to_string_lossy().to_string(),
+                entry.metadata()?,
+            ))
+        }) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!(
+                    "Skipping unreadable entry of {}: {}",
+                    backups_path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        if name.starts_with('.') || !metadata.is_file() {
+            continue;
+        }
+        // Not every filesystem records birth time
+        let creation_time: DateTime<Local> =
+            match metadata.created().or_else(|_| metadata.modified()) {
+                Ok(time) => time.into(),
+                Err(e) => {
+                    warn!("Skipping backup {} without file times: {}", name, e);
+                    continue;
+                }
+            };
+
+        backup_files.push(SimpleDirEntry {
+            name,
+            creation_time: creation_time.naive_local(),
+            size: metadata.len(),
+            hr_size: humansize::format_size(metadata.len(), DECIMAL),
+        });
+    }
+    sort_backups(&mut backup_files, BackupSort::Date, false);
+
+    Ok(backup_files)
+}
+
+/// Archives save files of `source_path` into a new tar.gz or tar.zst file in `backups_path`.
+/// When `source_path` has a `saves` subdirectory, only that directory is archived, otherwise
+/// `source_path` is the save directory itself and its contents are archived. Backups directory
+/// is skipped when it is placed among the archived files.
+pub fn create_backup(
+    source_path: &Path,
+    backups_path: &Path,
+    compression: BackupCompression,
+) -> Result<PathBuf> {
+    let extension = match compression {
+        BackupCompression::Gzip => "tar.gz",
+        BackupCompression::Zstd => "tar.zst",
+    };
+    let (backup_path, file) = create_backup_file(backups_path, extension)?;
+
+    debug!(
+        "Creating backup of {} in {}",
+        source_path.display(),
+        backup_path.display()
+    );
+
+    let backups_path = backups_path.canonicalize()?;
+    match compression {
+        BackupCompression::Gzip => {
+            let mut archive = Builder::new(GzEncoder::new(file, Compression::default()));
+            append_saves(&mut archive, source_path, &backups_path)?;
+            archive.into_inner()?.finish()?;
+        }
+        BackupCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(file, 0)?;
+            // Lets verification detect corrupted data, as gzip CRC does
+            encoder.include_checksum(true)?;
+            let mut archive = Builder::new(encoder);
+            append_saves(&mut archive, source_path, &backups_path)?;
+            archive.into_inner()?.finish()?;
+        }
+    }
+
+    Ok(backup_path)
+}
+
+/// Creates a backup file named by current time, with a numeric suffix when backup of the same
+/// second already exists
+fn create_backup_file(backups_path: &Path, extension: &str) -> Result<(PathBuf, File)> {
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut name = format!("valman-backup-{}.{}", timestamp, extension);
+    for suffix in 1.. {
+        let backup_path = backups_path.join(&name);
+        match File::options()
+            .write(true)
+            .create_new(true)
+            .open(&backup_path)
+        {
+            Ok(file) => return Ok((backup_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                name = format!("valman-backup-{}-{}.{}", timestamp, suffix, extension);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    unreachable!("backup file names are exhausted")
+}
+
+fn append_saves<W: Write>(
+    archive: &mut Builder<W>,
+    source_path: &Path,
+    backups_path: &Path,
+) -> Result<()> {
+    let saves_path = source_path.join(worlds::SAVES_DIR_NAME);
+    if saves_path.is_dir() {
+        return append_dir(
+            archive,
+            &saves_path,
+            Path::new(worlds::SAVES_DIR_NAME),
+            backups_path,
+        );
+    }
+
+    append_dir(archive, source_path, Path::new(""), backups_path)
+}
+
+/// Appends contents of `dir_path` under `archive_path`, skipping the backups directory
+fn append_dir<W: Write>(
+    archive: &mut Builder<W>,
+    dir_path: &Path,
+    archive_path: &Path,
+    backups_path: &Path,
+) -> Result<()> {
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = archive_path.join(entry.file_name());
+
+        if path.canonicalize()? == backups_path {
+            continue;
+        }
+
+        if path.is_dir() {
+            archive.append_dir(&name, &path)?;
+            append_dir(archive, &path, &name, backups_path)?;
+        } else {
+            archive.append_path_with_name(&path, &name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Decompressing reader of tar stream of backup in given format
+fn tar_stream<'a, R: Read + 'a>(format: BackupFormat, reader: R) -> Result<Box<dyn Read + 'a>> {
+    Ok(match format {
+        BackupFormat::TarGz => Box::new(GzDecoder::new(reader)),
+        BackupFormat::TarZst => Box::new(zstd::Decoder::new(reader)?),
+        BackupFormat::Tar => Box::new(reader),
+        BackupFormat::Zip => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Zip backup is not a tar stream",
+            )
+            .into())
+        }
+    })
+}
+
+fn open_zip(backup_path: &Path) -> Result<ZipArchive<File>> {
+    Ok(ZipArchive::new(File::open(backup_path)?).map_err(io::Error::from)?)
+}
+
+/// Lists files stored in backup archive, directories are left out
+pub fn list_backup_entries(backup_path: &Path) -> Result<Vec<BackupEntry>> {
+    let format = BackupFormat::detect(backup_path)?;
+    if format == BackupFormat::Zip {
+        return list_zip_entries(backup_path);
+    }
+    let mut archive = Archive::new(tar_stream(format, File::open(backup_path)?)?);
+
+    let mut entries = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let size = entry.header().size()?;
+        let modification_time = entry
+            .header()
+            .mtime()
+            .ok()
+            .and_then(|mtime| Local.timestamp_opt(mtime as i64, 0).single())
+            .map(|mtime| mtime.naive_local());
+
+        entries.push(BackupEntry {
+            path: entry.path()?.to_string_lossy().to_string(),
+            size,
+            hr_size: humansize::format_size(size, DECIMAL),
+            modification_time,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn list_zip_entries(backup_path: &Path) -> Result<Vec<BackupEntry>> {
+    let mut archive = open_zip(backup_path)?;
+
+    let mut entries = vec![];
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i).map_err(io::Error::from)?;
+        if file.is_dir() {
+            continue;
+        }
+        // Zip keeps local time without time zone
+        let mtime = file.last_modified();
+        let modification_time = NaiveDate::from_ymd_opt(
+            mtime.year().into(),
+            mtime.month().into(),
+            mtime.day().into(),
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
+                mtime.hour().into(),
+                mtime.minute().into(),
+                mtime.second().into(),
+            )
+        });
+
+        entries.push(BackupEntry {
+            path: file.name().to_string(),
+            size: file.size(),
+            hr_size: humansize::format_size(file.size(), DECIMAL),
+            modification_time,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Reads backup archive index: its files, worlds found among them and their total size
+pub fn read_backup_contents(backup_path: &Path) -> Result<BackupContents> {
+    let entries = list_backup_entries(backup_path)?;
+    let worlds = worlds::world_names(entries.iter().map(|entry| entry.path.as_str()));
+    let total_size = entries.iter().map(|entry| entry.size).sum();
+
+    Ok(BackupContents {
+        entries,
+        worlds,
+        total_size,
+        hr_total_size: humansize::format_size(total_size, DECIMAL),
+    })
+}
+
+/// Restores only the chosen files of backup archive to `destination_path`. With `dry_run` nothing is written,
+/// returned entries tell which files would be restored and overwritten.
+pub fn restore_backup_entries(
+    backup_path: &Path,
+    destination_path: &Path,
+    paths: &[String],
+    dry_run: bool,
+) -> Result<Vec<RestoredEntry>> {
+    debug!(
+        "Restoring {} entries of backup {} to {}{}",
+        paths.len(),
+        backup_path.display(),
+        destination_path.display(),
+        if dry_run { " (dry run)" } else { "" }
+    );
+
+    // Check all files are present and stay inside the destination first, so a bad selection never
+    // ends with partial restore
+    if let Some(path) = paths.iter().find(|path| {
+        !Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Backup entry {} points outside of the destination", path),
+        )
+        .into());
+    }
+    let archived: HashSet<_> = list_backup_entries(backup_path)?
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    let mut missing: Vec<_> = paths
+        .iter()
+        .filter(|path| !archived.contains(*path))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        missing.sort_unstable();
+
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Files not found in backup: {}", missing.join(", ")),
+        )
+        .into());
+    }
+
+    let format = BackupFormat::detect(backup_path)?;
+    if format == BackupFormat::Zip {
+        return restore_zip_entries(backup_path, destination_path, paths, dry_run);
+    }
+    let mut remaining: HashSet<&str> = paths.iter().map(String::as_str).collect();
+    let mut archive = Archive::new(tar_stream(format, File::open(backup_path)?)?);
+    // unpack_in needs existing destination, zip restore creates it as well
+    if !dry_run {
+        fs::create_dir_all(destination_path)?;
+    }
+
+    let mut restored = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        if !entry.header().entry_type().is_file() || !remaining.remove(path.as_str()) {
+            continue;
+        }
+
+        let overwritten = destination_path.join(&path).exists();
+        // unpack_in refuses paths escaping the destination
+        if !dry_run && !entry.unpack_in(destination_path)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Backup entry {} points outside of the destination", path),
+            )
+            .into());
+        }
+
+        restored.push(RestoredEntry { path, overwritten });
+    }
+
+    Ok(restored)
+}
+
+fn restore_zip_entries(
+    backup_path: &Path,
+    destination_path: &Path,
+    paths: &[String],
+    dry_run: bool,
+) -> Result<Vec<RestoredEntry>> {
+    let mut archive = open_zip(backup_path)?;
+    let paths: BTreeSet<&String> = paths.iter().collect();
+
+    let mut restored = vec![];
+    for path in paths {
+        let mut file = archive.by_name(path).map_err(io::Error::from)?;
+        // Same protection as tar unpack_in, names escaping the destination are refused
+        let Some(file_path) = file.enclosed_name().map(|name| destination_path.join(name)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Backup entry {} points outside of the destination", path),
+            )
+            .into());
+        };
+
+        let overwritten = file_path.exists();
+        if !dry_run {
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&file_path)?)?;
+        }
+
+        restored.push(RestoredEntry {
+            path: path.clone(),
+            overwritten,
+        });
+    }
+
+    Ok(restored)
+}
+
+/// Computes SHA-256 checksum of backup file, as lowercase hex
+pub fn backup_checksum(backup_path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(backup_path)?, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Reads the whole backup without writing anything: decompresses it and walks every archive entry
+/// including its data, so truncated or corrupted archives are detected. Returns SHA-256 checksum
+/// of the backup file computed along the way.
+pub fn verify_backup(backup_path: &Path) -> Result<String> {
+    debug!("Verifying backup {}", backup_path.display());
+
+    read_whole_backup(backup_path).map(|(sha256, _)| sha256)
+}
+
+/// Checks that uploaded file is a readable backup with at least one world in it.
+/// Returns SHA-256 checksum of the file and names of the worlds.
+pub fn validate_uploaded_backup(backup_path: &Path) -> Result<(String, Vec<String>)> {
+    debug!("Validating uploaded backup {}", backup_path.display());
+
+    let (sha256, paths) = read_whole_backup(backup_path)?;
+    let worlds = worlds::world_names(paths.iter().map(String::as_str));
+    if worlds.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Backup contains no world files (.fwl or .db)",
+        )
+        .into());
+    }
+
+    Ok((sha256, worlds))
+}
+
+/// Decompresses backup and reads all of its entries, returns checksum of the file and paths of
+/// files in the archive
+fn read_whole_backup(backup_path: &Path) -> Result<(String, Vec<String>)> {
+    let format = BackupFormat::detect(backup_path)?;
+    if format == BackupFormat::Zip {
+        return read_whole_zip(backup_path);
+    }
+    let mut reader = HashingReader::new(File::open(backup_path)?);
+    let mut archive = Archive::new(tar_stream(format, &mut reader)?);
+
+    let mut paths = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() {
+            paths.push(entry.path()?.to_string_lossy().to_string());
+        }
+        io::copy(&mut entry, &mut io::sink())?;
+    }
+    // Read compressed stream to its end, so its checksum trailer is checked as well
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    // Anything left after the compressed stream still belongs to the file checksum
+    io::copy(&mut reader, &mut io::sink())?;
+
+    Ok((reader.finish(), paths))
+}
+
+/// Zip is read by random access, so unlike tar streams its checksum takes a separate pass
+fn read_whole_zip(backup_path: &Path) -> Result<(String, Vec<String>)> {
+    let mut archive = open_zip(backup_path)?;
+
+    let mut paths = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(io::Error::from)?;
+        if !file.is_dir() {
+            paths.push(file.name().to_string());
+        }
+        // Reading to the end checks CRC of the file
+        io::copy(&mut file, &mut io::sink())?;
+    }
+
+    Ok((backup_checksum(backup_path)?, paths))
+}
+
+/// Reader computing SHA-256 of all bytes read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+/// Removes backup file, returns its size
+pub fn delete_backup(backup_path: &Path) -> Result<u64> {
+    debug!("Deleting backup {}", backup_path.display());
+
+    let size = fs::metadata(backup_path)?.len();
+    fs::remove_file(backup_path)?;
+
+    Ok(size)
+}
 
 pub fn restore_backup(backup_path: &Path, destination_path: &Path) -> Result<()> {
     debug!(
@@ -13,10 +635,434 @@ pub fn restore_backup(backup_path: &Path, destination_path: &Path) -> Result<()>
         destination_path.display()
     );
 
-    let tar_gz = File::open(backup_path)?;
-    let tar = GzDecoder::new(tar_gz);
-    let mut archive = Archive::new(tar);
-    archive.unpack(destination_path)?;
+    match BackupFormat::detect(backup_path)? {
+        BackupFormat::Zip => open_zip(backup_path)?
+            .extract(destination_path)
+            .map_err(io::Error::from)?,
+        format => {
+            Archive::new(tar_stream(format, File::open(backup_path)?)?).unpack(destination_path)?
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use tar::Header;
+
+    use super::*;
+
+    /// Writes tar.gz backup with given files, names are stored as is so they can escape the destination
+    fn write_tar_gz(path: &Path, files: &[(&str, &str)]) {
+        let mut archive = Builder::new(GzEncoder::new(
+            File::create(path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, contents) in files {
+            let mut header = Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive.append(&header, contents.as_bytes()).unwrap();
+        }
+        archive
+            .into_inner()
+            .unwrap()
+            .finish()
+            .unwrap()
+            .flush()
+            .unwrap();
+    }
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    fn write_zip(path: &Path, files: &[(&str, &str)]) {
+        let mut archive = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            archive.start_file(*name, Default::default()).unwrap();
+            archive.write_all(contents.as_bytes()).unwrap();
+        }
+        archive.finish().unwrap();
+    }
+
+    /// Writes `contents` to a file named misleadingly, detection must not rely on the name
+    fn detect(contents: &[u8]) -> Result<BackupFormat> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.zip");
+        fs::write(&path, contents).unwrap();
+
+        BackupFormat::detect(&path)
+    }
+
+    fn tar() -> Vec<u8> {
+        let mut archive = Builder::new(vec![]);
+        let mut header = Header::new_gnu();
+        header.set_size(5);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, "worlds_local/Test.fwl", &b"world"[..])
+            .unwrap();
+
+        archive.into_inner().unwrap()
+    }
+
+    /// Restores whole backup and selected files of it, checking both give the archived contents
+    fn assert_round_trip(backup_path: &Path) {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut listed: Vec<_> = list_backup_entries(backup_path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path, entry.size))
+            .collect();
+        listed.sort_unstable();
+        assert_eq!(
+            listed,
+            [
+                ("worlds_local/Midgard.db".to_string(), 2),
+                ("worlds_local/Midgard.fwl".to_string(), 3),
+            ]
+        );
+
+        let destination = dir.path().join("full");
+        restore_backup(backup_path, &destination).unwrap();
+        assert_eq!(
+            fs::read_to_string(destination.join("worlds_local/Midgard.db")).unwrap(),
+            "db"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("worlds_local/Midgard.fwl")).unwrap(),
+            "fwl"
+        );
+
+        let destination = dir.path().join("selected");
+        let selected = paths(&["worlds_local/Midgard.fwl"]);
+        let restored = restore_backup_entries(backup_path, &destination, &selected, false).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            fs::read_to_string(destination.join("worlds_local/Midgard.fwl")).unwrap(),
+            "fwl"
+        );
+        assert!(!destination.join("worlds_local/Midgard.db").exists());
+    }
+
+    /// Creates backup of saves directory containing the backups directory, which must be skipped
+    fn create_test_backup(compression: BackupCompression) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let source_path = dir.path().join("saves");
+        let backups_path = source_path.join("backups");
+        fs::create_dir_all(source_path.join("worlds_local")).unwrap();
+        fs::create_dir_all(&backups_path).unwrap();
+        fs::write(source_path.join("worlds_local/Midgard.db"), "db").unwrap();
+        fs::write(source_path.join("worlds_local/Midgard.fwl"), "fwl").unwrap();
+
+        let backup_path = create_backup(&source_path, &backups_path, compression).unwrap();
+        assert_eq!(list_backups(&backups_path).unwrap().len(), 1);
+
+        (dir, backup_path)
+    }
+
+    #[test]
+    fn only_saves_subdirectory_is_archived() {
+        let dir = tempfile::tempdir().unwrap();
+        let source_path = dir.path().join("valheim");
+        let backups_path = source_path.join("backups");
+        fs::create_dir_all(source_path.join("saves/worlds_local")).unwrap();
+        fs::create_dir_all(source_path.join("server")).unwrap();
+        fs::create_dir_all(&backups_path).unwrap();
+        fs::write(source_path.join("saves/worlds_local/Midgard.db"), "db").unwrap();
+        fs::write(source_path.join("server/valheim_server.x86_64"), "binary").unwrap();
+
+        let backup_path =
+            create_backup(&source_path, &backups_path, BackupCompression::Gzip).unwrap();
+        let entries = list_backup_entries(&backup_path).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.path.as_str())
+                .collect::<Vec<_>>(),
+            ["saves/worlds_local/Midgard.db"]
+        );
+    }
+
+    #[test]
+    fn backups_of_the_same_second_do_not_overwrite_each_other() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..3)
+            .map(|_| create_backup_file(dir.path(), "tar.gz").unwrap().0)
+            .collect::<HashSet<_>>();
+
+        assert_eq!(paths.len(), 3);
+        assert_eq!(list_backups(dir.path()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn only_selected_entries_are_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.tar.gz");
+        write_tar_gz(
+            &backup_path,
+            &[
+                ("worlds_local/Midgard.db", "db"),
+                ("worlds_local/Midgard.fwl", "fwl"),
+                ("worlds_local/Asgard.db", "other"),
+            ],
+        );
+        let destination = dir.path().join("saves");
+        fs::create_dir_all(destination.join("worlds_local")).unwrap();
+        fs::write(destination.join("worlds_local/Midgard.db"), "old").unwrap();
+
+        let selected = paths(&["worlds_local/Midgard.db", "worlds_local/Midgard.fwl"]);
+        let restored =
+            restore_backup_entries(&backup_path, &destination, &selected, false).unwrap();
+
+        let restored: Vec<_> = restored
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.overwritten))
+            .collect();
+        assert_eq!(
+            restored,
+            [
+                ("worlds_local/Midgard.db", true),
+                ("worlds_local/Midgard.fwl", false),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("worlds_local/Midgard.db")).unwrap(),
+            "db"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("worlds_local/Midgard.fwl")).unwrap(),
+            "fwl"
+        );
+        assert!(!destination.join("worlds_local/Asgard.db").exists());
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.tar.gz");
+        write_tar_gz(&backup_path, &[("Midgard.db", "db")]);
+        let destination = dir.path().join("saves");
+
+        let restored =
+            restore_backup_entries(&backup_path, &destination, &paths(&["Midgard.db"]), true)
+                .unwrap();
+
+        assert_eq!(restored.len(), 1);
+        assert!(!destination.exists());
+    }
+
+    #[test]
+    fn missing_entry_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.tar.gz");
+        write_tar_gz(&backup_path, &[("Midgard.db", "db")]);
+        let destination = dir.path().join("saves");
+
+        let selected = paths(&["Midgard.db", "Midgard.fwl"]);
+        assert!(restore_backup_entries(&backup_path, &destination, &selected, false).is_err());
+        assert!(!destination.join("Midgard.db").exists());
+    }
+
+    #[test]
+    fn tar_entry_escaping_destination_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.tar.gz");
+        write_tar_gz(
+            &backup_path,
+            &[("Midgard.db", "db"), ("../evil.db", "evil")],
+        );
+        let destination = dir.path().join("saves");
+        fs::create_dir_all(&destination).unwrap();
+
+        for dry_run in [true, false] {
+            let selected = paths(&["Midgard.db", "../evil.db"]);
+            assert!(
+                restore_backup_entries(&backup_path, &destination, &selected, dry_run).is_err()
+            );
+        }
+        assert!(!dir.path().join("evil.db").exists());
+        assert!(!destination.join("Midgard.db").exists());
+    }
+
+    #[test]
+    fn uploaded_backup_with_worlds_is_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.tar.gz");
+        write_tar_gz(
+            &backup_path,
+            &[
+                ("worlds_local/Midgard.db", "db"),
+                ("worlds_local/Midgard.fwl", "fwl"),
+                ("worlds_local/Asgard.fwl", "fwl"),
+                ("adminlist.txt", ""),
+            ],
+        );
+
+        let (sha256, worlds) = validate_uploaded_backup(&backup_path).unwrap();
+
+        assert_eq!(
+            sha256,
+            format!("{:x}", Sha256::digest(fs::read(&backup_path).unwrap()))
+        );
+        assert_eq!(worlds, ["Asgard", "Midgard"]);
+    }
+
+    #[test]
+    fn uploaded_backup_without_worlds_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.tar.gz");
+        write_tar_gz(&backup_path, &[("adminlist.txt", ""), ("Midgard.txt", "")]);
+
+        assert!(validate_uploaded_backup(&backup_path).is_err());
+    }
+
+    #[test]
+    fn corrupted_uploaded_backup_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.tar.gz");
+        write_tar_gz(
+            &backup_path,
+            &[("Midgard.db", "db"), ("Midgard.fwl", "fwl")],
+        );
+        let contents = fs::read(&backup_path).unwrap();
+
+        fs::write(&backup_path, &contents[..contents.len() - 4]).unwrap();
+        assert!(validate_uploaded_backup(&backup_path).is_err());
+
+        fs::write(&backup_path, "not a backup").unwrap();
+        assert!(validate_uploaded_backup(&backup_path).is_err());
+    }
+
+    #[test]
+    fn detects_archives_by_contents() {
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(&tar()).unwrap();
+        let tar_gz = gzip.finish().unwrap();
+        let tar_zst = zstd::encode_all(&tar()[..], 0).unwrap();
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        zip.start_file("worlds_local/Test.fwl", Default::default())
+            .unwrap();
+        zip.write_all(b"world").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        let empty_zip = zip::ZipWriter::new(io::Cursor::new(vec![]))
+            .finish()
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(detect(&tar_gz).unwrap(), BackupFormat::TarGz);
+        assert_eq!(detect(&tar_zst).unwrap(), BackupFormat::TarZst);
+        assert_eq!(detect(&tar()).unwrap(), BackupFormat::Tar);
+        assert_eq!(detect(&zip).unwrap(), BackupFormat::Zip);
+        assert_eq!(detect(&empty_zip).unwrap(), BackupFormat::Zip);
+    }
+
+    #[test]
+    fn rejects_unknown_and_truncated_files() {
+        assert!(detect(b"not a backup").is_err());
+        assert!(detect(b"").is_err());
+        // Too short to reach tar magic
+        assert!(detect(&tar()[..100]).is_err());
+    }
+
+    #[test]
+    fn gzip_backup_round_trip() {
+        let (_dir, backup_path) = create_test_backup(BackupCompression::Gzip);
+
+        assert_eq!(
+            BackupFormat::detect(&backup_path).unwrap(),
+            BackupFormat::TarGz
+        );
+        assert!(backup_path.to_string_lossy().ends_with(".tar.gz"));
+        assert_round_trip(&backup_path);
+    }
+
+    #[test]
+    fn zstd_backup_round_trip() {
+        let (_dir, backup_path) = create_test_backup(BackupCompression::Zstd);
+
+        assert_eq!(
+            BackupFormat::detect(&backup_path).unwrap(),
+            BackupFormat::TarZst
+        );
+        assert!(backup_path.to_string_lossy().ends_with(".tar.zst"));
+        assert_round_trip(&backup_path);
+    }
+
+    #[test]
+    fn zip_backup_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.zip");
+        write_zip(
+            &backup_path,
+            &[
+                ("worlds_local/Midgard.db", "db"),
+                ("worlds_local/Midgard.fwl", "fwl"),
+            ],
+        );
+
+        assert_round_trip(&backup_path);
+    }
+
+    #[test]
+    fn zip_entry_escaping_destination_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.zip");
+        write_zip(
+            &backup_path,
+            &[("Midgard.db", "db"), ("../evil.db", "evil")],
+        );
+        let destination = dir.path().join("saves");
+        fs::create_dir_all(&destination).unwrap();
+
+        for dry_run in [true, false] {
+            let selected = paths(&["Midgard.db", "../evil.db"]);
+            assert!(
+                restore_backup_entries(&backup_path, &destination, &selected, dry_run).is_err()
+            );
+        }
+        assert!(!dir.path().join("evil.db").exists());
+        assert!(!destination.join("Midgard.db").exists());
+    }
+
+    #[test]
+    fn backups_are_sorted_by_given_key_with_ties_by_name() {
+        let time = |day| {
+            NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        let entry = |name: &str, day, size| SimpleDirEntry {
+            name: name.to_string(),
+            creation_time: time(day),
+            size,
+            hr_size: String::new(),
+        };
+        let mut backups = vec![entry("b", 2, 10), entry("c", 1, 30), entry("a", 2, 30)];
+        let names = |backups: &[SimpleDirEntry]| {
+            backups
+                .iter()
+                .map(|backup| backup.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        sort_backups(&mut backups, BackupSort::Date, false);
+        assert_eq!(names(&backups), ["c", "a", "b"]);
+        sort_backups(&mut backups, BackupSort::Date, true);
+        assert_eq!(names(&backups), ["b", "a", "c"]);
+        sort_backups(&mut backups, BackupSort::Size, false);
+        assert_eq!(names(&backups), ["b", "a", "c"]);
+        sort_backups(&mut backups, BackupSort::Size, true);
+        assert_eq!(names(&backups), ["c", "a", "b"]);
+        sort_backups(&mut backups, BackupSort::Name, false);
+        assert_eq!(names(&backups), ["a", "b", "c"]);
+        sort_backups(&mut backups, BackupSort::Name, true);
+        assert_eq!(names(&backups), ["c", "b", "a"]);
+    }
+}
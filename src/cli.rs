@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Dashboard for managing dockerized Valheim dedicated server"
+)]
+pub struct Cli {
+    /// Path to configuration file, every property can be also set with VALMAN_<PROPERTY> variable
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "VALMAN_CONFIG",
+        default_value = "config.toml"
+    )]
+    pub config: PathBuf,
+
+    /// Path to log4rs configuration file
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "VALMAN_LOG_CONFIG",
+        default_value = "log4rs.yml"
+    )]
+    pub log_config: PathBuf,
+
+    /// Id of the server to manage, the first configured server is used when omitted
+    #[arg(short, long, global = true, env = "VALMAN_SERVER")]
+    pub server: Option<String>,
+
+    /// Command to run, web server is started when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start web server
+    Serve,
+    /// Show container and Valheim server status
+    Status,
+    /// Restart Valheim server container
+    Restart {
+        /// Restart even when the last restart happened less than `restart_delay_seconds` ago
+        #[arg(long)]
+        force: bool,
+    },
+    /// Manage backups
+    #[command(subcommand)]
+    Backup(BackupCommand),
+    /// Print Valheim server container logs
+    Logs {
+        /// Keep printing new log lines as they arrive
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Number of last lines to print, defaults to `last_log_lines_count`
+        #[arg(short = 'n', long)]
+        lines: Option<usize>,
+    },
+    /// Update Valheim server container to the newest image, taking a backup first
+    Update {
+        /// Only check whether a newer image is available
+        #[arg(long)]
+        check: bool,
+    },
+    /// Validate configuration, report unknown and deprecated keys and check that configured paths exist
+    CheckConfig,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupCommand {
+    /// Archive contents of `valheim_backups_destination_path` into a new backup
+    Create,
+    /// List backups, oldest first
+    List,
+    /// Read the whole backup to check it is not corrupted and record the result
+    Verify {
+        /// Backup file name in `valheim_backups_path`
+        name: String,
+    },
+    /// Restore backup and restart Valheim server container
+    Restore {
+        /// Backup file name in `valheim_backups_path`
+        name: String,
+    },
+}
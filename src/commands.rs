@@ -0,0 +1,438 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+use chrono::Local;
+use docker_api::Docker;
+
+use crate::{
+    backup,
+    cli::{BackupCommand, Command},
+    config::{Config, ServerConfig},
+    docker,
+    error::{Result, ValmanError},
+    store::{RestartEntry, RestartTrigger, Store},
+    valve,
+};
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const CLI_TRIGGERED_BY: &str = "cli";
+
+/// Runs administrative command against configured server, without the web server
+pub async fn run(config_path: &Path, server_id: Option<&str>, command: &Command) -> ExitCode {
+    let config = match Config::load(config_path) {
+        Ok((config, warnings)) => {
+            for warning in warnings {
+                eprintln!("warning: {}", warning);
+            }
+
+            config
+        }
+        Err(e) => {
+            eprintln!("error: invalid configuration: {}", e);
+
+            return ExitCode::FAILURE;
+        }
+    };
+    let server = match server_id {
+        Some(server_id) => match config.server(server_id) {
+            Some(server) => server,
+            None => {
+                eprintln!("error: unknown server {}", server_id);
+
+                return ExitCode::FAILURE;
+            }
+        },
+        None => config.default_server(),
+    };
+    let docker = match docker::connect(&config) {
+        Ok(docker) => docker,
+        Err(e) => {
+            eprintln!("error: {}", e);
+
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match command {
+        Command::Status => status(&config, server, &docker).await,
+        Command::Restart { force } => restart(&config, server, &docker, *force).await,
+        Command::Backup(BackupCommand::Create) => backup_create(&config, server).await,
+        Command::Backup(BackupCommand::List) => backup_list(&config, server),
+        Command::Backup(BackupCommand::Verify { name }) => {
+            backup_verify(&config, server, name).await
+        }
+        Command::Backup(BackupCommand::Restore { name }) => {
+            backup_restore(&config, server, &docker, name).await
+        }
+        Command::Logs { follow, lines } => logs(&config, server, &docker, *follow, *lines).await,
+        Command::Update { check } => update(&config, server, &docker, *check).await,
+        Command::Serve | Command::CheckConfig => unreachable!("handled by main"),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn status(config: &Config, server: &ServerConfig, docker: &Docker) -> Result<()> {
+    println!("Server:         {} ({})", server.name, server.id);
+
+    match docker::retrieve_container_status(docker, &server.container).await {
+        Ok(container_status) => {
+            println!(
+                "Container:      {} ({})",
+                container_status.state,
+                container_status.uptime()
+            );
+            if let Some(health) = &container_status.health {
+                println!("Health:         {}", health);
+            }
+            if let Some(oom_killed_at) = container_status.oom_killed_at {
+                println!("OOM killed:     {}", oom_killed_at.format(DATETIME_FORMAT));
+            }
+        }
+        Err(e) => println!("Container:      n/a ({})", e),
+    }
+
+    let a2s_client = a2s::A2SClient::new().await?;
+    match valve::retrieve_valve_info(&a2s_client, &server.valheim_server_address).await {
+        Ok(valve_info) => println!(
+            "Valheim server: {}, version {}, {}/{} players",
+            valve_info.server_name,
+            valve_info.version,
+            valve_info.player_count,
+            valve_info.max_player_count
+        ),
+        Err(e) => println!("Valheim server: n/a ({})", e),
+    }
+
+    let server_state = Store::load(&config.data_path)?.server(&server.id)?;
+    println!(
+        "Last restart:   {}",
+        server_state
+            .last_restart_time
+            .map(|rt| rt.format(DATETIME_FORMAT).to_string())
+            .unwrap_or_else(|| "n/a".to_string())
+    );
+
+    Ok(())
+}
+
+async fn restart(
+    config: &Config,
+    server: &ServerConfig,
+    docker: &Docker,
+    force: bool,
+) -> Result<()> {
+    let store = Store::load(&config.data_path)?;
+    if !force
+        && !store
+            .server(&server.id)?
+            .restart_allowed(server.restart_delay_seconds)
+    {
+        return Err(ValmanError::RestartTooSoon(server.restart_delay_seconds));
+    }
+
+    docker::restart_container(docker, &server.container).await?;
+    record_restart(&store, server, RestartTrigger::Manual)?;
+
+    println!("Container {} restarted", server.container);
+
+    Ok(())
+}
+
+async fn backup_create(config: &Config, server: &ServerConfig) -> Result<()> {
+    let source_path = server.valheim_backups_destination_path.clone();
+    let backups_path = server.valheim_backups_path.clone();
+    let compression = server.backup_compression;
+
+    let (backup_path, sha256) = tokio::task::spawn_blocking(move || {
+        let backup_path = backup::create_backup(&source_path, &backups_path, compression)?;
+        let sha256 = backup::backup_checksum(&backup_path)?;
+
+        Ok::<_, ValmanError>((backup_path, sha256))
+    })
+    .await
+    .expect("backup task")?;
+
+    Store::load(&config.data_path)?.update(|state| {
+        state
+            .server_mut(&server.id)
+            .record_backup_checksum(&backup::backup_name(&backup_path), sha256.clone())
+    })?;
+
+    println!(
+        "Backup created: {} (SHA-256 {})",
+        backup_path.display(),
+        sha256
+    );
+
+    Ok(())
+}
+
+fn backup_list(config: &Config, server: &ServerConfig) -> Result<()> {
+    let server_state = Store::load(&config.data_path)?.server(&server.id)?;
+
+    for backup_file in backup::list_backups(&server.valheim_backups_path)? {
+        let record = server_state.backups.get(&backup_file.name);
+        let integrity = match record {
+            Some(record) if record.verified => "verified",
+            Some(record) if record.verification_error.is_some() => "failed",
+            _ => "not verified",
+        };
+        let mut tags: Vec<_> = record
+            .map(|record| record.labels.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        if record.is_some_and(|record| record.pinned) {
+            tags.insert(0, "pinned");
+        }
+
+        println!(
+            "{}  {:>10}  {:<12}  {}{}",
+            backup_file.creation_time.format(DATETIME_FORMAT),
+            backup_file.hr_size,
+            integrity,
+            backup_file.name,
+            if tags.is_empty() {
+                String::new()
+            } else {
+                format!("  [{}]", tags.join(", "))
+            }
+        );
+    }
+
+    Ok(())
+}
+
+async fn backup_verify(config: &Config, server: &ServerConfig, name: &str) -> Result<()> {
+    let backup_path = server.valheim_backups_path.join(name);
+    // Keep failed lookups out of the recorded verification results
+    File::open(&backup_path)?;
+
+    let result = tokio::task::spawn_blocking(move || backup::verify_backup(&backup_path))
+        .await
+        .expect("verify task");
+
+    let record = Store::load(&config.data_path)?.update(|state| {
+        let server_state = state.server_mut(&server.id);
+        server_state.record_backup_verification(name, result);
+
+        server_state.backups[name].clone()
+    })?;
+
+    match record.verification_error {
+        None => {
+            println!(
+                "Backup {} verified (SHA-256 {})",
+                name,
+                record.sha256.unwrap_or_default()
+            );
+
+            Ok(())
+        }
+        Some(error) => Err(ValmanError::Backup(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Backup {} failed verification: {}", name, error),
+        ))),
+    }
+}
+
+async fn backup_restore(
+    config: &Config,
+    server: &ServerConfig,
+    docker: &Docker,
+    name: &str,
+) -> Result<()> {
+    let backup_path = server.valheim_backups_path.join(name);
+    let destination_path = server.valheim_backups_destination_path.clone();
+
+    tokio::task::spawn_blocking(move || backup::restore_backup(&backup_path, &destination_path))
+        .await
+        .expect("restore task")?;
+    println!("Backup {} restored", name);
+
+    docker::restart_container(docker, &server.container).await?;
+    let store = Store::load(&config.data_path)?;
+    record_restart(&store, server, RestartTrigger::Restore)?;
+
+    println!("Container {} restarted", server.container);
+
+    Ok(())
+}
+
+async fn logs(
+    config: &Config,
+    server: &ServerConfig,
+    docker: &Docker,
+    follow: bool,
+    lines: Option<usize>,
+) -> Result<()> {
+    let lines = lines.unwrap_or(config.last_log_lines_count as usize);
+    let mut stdout = io::stdout();
+
+    docker::stream_container_logs(docker, &server.container, lines, follow, |chunk| {
+        let _ = stdout.write_all(chunk);
+        let _ = stdout.flush();
+    })
+    .await
+}
+
+async fn update(
+    config: &Config,
+    server: &ServerConfig,
+    docker: &Docker,
+    check: bool,
+) -> Result<()> {
+    let image_status = docker::check_image_update(docker, &server.container).await?;
+    if let Some(registry_error) = &image_status.registry_error {
+        eprintln!("warning: registry check failed: {}", registry_error);
+    }
+    if !image_status.update_available {
+        println!("Image {} is up to date", image_status.image);
+
+        return Ok(());
+    }
+
+    println!("Image {} has an update available", image_status.image);
+    if check {
+        return Ok(());
+    }
+
+    let container = docker::ensure_recreatable(&server.container)?;
+    backup_create(config, server).await?;
+    docker::update_container(docker, container).await?;
+    let store = Store::load(&config.data_path)?;
+    record_restart(&store, server, RestartTrigger::Update)?;
+
+    println!("Container {} updated", server.container);
+
+    Ok(())
+}
+
+fn record_restart(store: &Store, server: &ServerConfig, trigger: RestartTrigger) -> Result<()> {
+    store.update(|state| {
+        state.server_mut(&server.id).record_restart(RestartEntry {
+            time: Local::now().naive_local(),
+            trigger,
+            triggered_by: Some(CLI_TRIGGERED_BY.to_string()),
+            recovery_seconds: None,
+        })
+    })
+}
+
+/// Loads configuration the same way web server does and checks that configured paths exist
+pub fn check_config(config_path: &Path) -> ExitCode {
+    println!("Checking configuration {}", config_path.display());
+
+    if !config_path.is_file() {
+        println!(
+            "warning: {} not found, using defaults and environment only",
+            config_path.display()
+        );
+    }
+
+    let (config, warnings) = match Config::load(config_path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("error: {}", e);
+
+            return ExitCode::FAILURE;
+        }
+    };
+    for warning in &warnings {
+        println!("warning: {}", warning);
+    }
+
+    let mut errors = vec![];
+    match config
+        .docker_host
+        .as_deref()
+        .map(|host| host.split_once("://"))
+    {
+        None => {
+            if !Path::new(&config.docker_socket_path).exists() {
+                errors.push(format!(
+                    "Docker socket {} does not exist",
+                    config.docker_socket_path
+                ));
+            }
+        }
+        Some(Some(("unix", socket_path))) => {
+            if !Path::new(socket_path).exists() {
+                errors.push(format!("Docker socket {} does not exist", socket_path));
+            }
+        }
+        Some(Some(("https", _))) => {
+            let cert_path = config.docker_cert_path.clone().unwrap_or_default();
+            let mut cert_files = vec!["cert.pem", "key.pem"];
+            if config.docker_tls_verify {
+                cert_files.push("ca.pem");
+            }
+
+            for cert_file in cert_files {
+                if !cert_path.join(cert_file).is_file() {
+                    errors.push(format!(
+                        "Docker TLS file {} does not exist",
+                        cert_path.join(cert_file).display()
+                    ));
+                }
+            }
+        }
+        Some(_) => {}
+    }
+    for server in &config.servers {
+        if !server.valheim_backups_path.is_dir() {
+            errors.push(format!(
+                "valheim_backups_path {} of server {} is not a directory",
+                server.valheim_backups_path.display(),
+                server.id
+            ));
+        }
+        if !server.valheim_backups_destination_path.is_dir() {
+            errors.push(format!(
+                "valheim_backups_destination_path {} of server {} is not a directory",
+                server.valheim_backups_destination_path.display(),
+                server.id
+            ));
+        }
+    }
+    if !config.template_path.is_file() {
+        println!(
+            "note: template_path {} not found, embedded templates will be used",
+            config.template_path.display()
+        );
+    }
+    if !config.data_path.is_dir() {
+        println!(
+            "note: data_path {} does not exist yet, it will be created",
+            config.data_path.display()
+        );
+    }
+
+    for error in &errors {
+        println!("error: {}", error);
+    }
+
+    if errors.is_empty() {
+        println!("Configuration OK ({} warning(s))", warnings.len());
+
+        ExitCode::SUCCESS
+    } else {
+        println!(
+            "Configuration has {} error(s) and {} warning(s)",
+            errors.len(),
+            warnings.len()
+        );
+
+        ExitCode::FAILURE
+    }
+}
@@ -1,34 +1,871 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 
 use config as cfg;
-use serde::Deserialize;
+use log::warn;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
+#[serde(try_from = "RawConfig")]
 pub struct Config {
     pub server_address: SocketAddr,
     pub docker_socket_path: String,
-    pub container_name: String,
+    pub docker_host: Option<String>,
+    pub docker_cert_path: Option<PathBuf>,
+    pub docker_tls_verify: bool,
     pub template_path: PathBuf,
+    pub last_log_lines_count: u32,
+    pub data_path: PathBuf,
+    pub username: String,
+    pub password: String,
+    /// Additional dashboard users, `username` and `password` define the admin user
+    pub users: Vec<UserConfig>,
+    pub servers: Vec<ServerConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UserConfig {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub role: UserRole,
+}
+
+/// Operators can do everything on the dashboard except actions that permanently remove data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    Admin,
+    #[default]
+    Operator,
+}
+
+/// Single managed Valheim server
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServerConfig {
+    pub id: String,
+    pub name: String,
+    pub container: ContainerSelector,
     pub valheim_server_address: SocketAddr,
     pub valheim_backups_path: PathBuf,
     pub valheim_backups_destination_path: PathBuf,
-    pub valheim_server_restart_delay_seconds: u32,
-    pub valheim_server_last_log_lines_count: u32,
-    pub username: String,
-    pub password: String,
+    pub restart_delay_seconds: u32,
+    pub backup_compression: BackupCompression,
+}
+
+/// Compression of backups created by valman, restore detects the format of each archive itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupCompression {
+    Gzip,
+    Zstd,
+}
+
+/// Way of locating server's Docker container
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerSelector {
+    Name(String),
+    Id(String),
+    /// Label in the form of `key` or `key=value`
+    Label(String),
+    Compose {
+        project: String,
+        service: String,
+    },
+}
+
+impl fmt::Display for ContainerSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerSelector::Name(name) => write!(f, "name {}", name),
+            ContainerSelector::Id(id) => write!(f, "id {}", id),
+            ContainerSelector::Label(label) => write!(f, "label {}", label),
+            ContainerSelector::Compose { project, service } => {
+                write!(f, "compose service {}/{}", project, service)
+            }
+        }
+    }
+}
+
+/// Config as written in the file. Server properties placed at top level are defaults for all entries
+/// in `servers`, or define the only server (with [`DEFAULT_SERVER_ID`]) when `servers` is not set.
+#[derive(Debug, Deserialize)]
+struct RawConfig {
+    server_address: SocketAddr,
+    docker_socket_path: String,
+    docker_host: Option<String>,
+    docker_cert_path: Option<PathBuf>,
+    docker_tls_verify: bool,
+    template_path: PathBuf,
+    last_log_lines_count: u32,
+    data_path: PathBuf,
+    username: String,
+    password: String,
+    users: Option<Vec<UserConfig>>,
+    container_name: Option<String>,
+    container_id: Option<String>,
+    container_label: Option<String>,
+    compose_project: Option<String>,
+    compose_service: Option<String>,
+    valheim_server_address: SocketAddr,
+    valheim_backups_path: Option<PathBuf>,
+    valheim_backups_destination_path: Option<PathBuf>,
+    restart_delay_seconds: u32,
+    backup_compression: BackupCompression,
+    servers: Option<Vec<RawServerConfig>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawServerConfig {
+    id: String,
+    name: Option<String>,
+    container_name: Option<String>,
+    container_id: Option<String>,
+    container_label: Option<String>,
+    compose_project: Option<String>,
+    compose_service: Option<String>,
+    valheim_server_address: Option<SocketAddr>,
+    valheim_backups_path: Option<PathBuf>,
+    valheim_backups_destination_path: Option<PathBuf>,
+    restart_delay_seconds: Option<u32>,
+    backup_compression: Option<BackupCompression>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = String;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let raw_servers = raw.servers.unwrap_or_else(|| {
+            vec![RawServerConfig {
+                id: DEFAULT_SERVER_ID.to_string(),
+                name: None,
+                container_name: None,
+                container_id: None,
+                container_label: None,
+                compose_project: None,
+                compose_service: None,
+                valheim_server_address: None,
+                valheim_backups_path: None,
+                valheim_backups_destination_path: None,
+                restart_delay_seconds: None,
+                backup_compression: None,
+            }]
+        });
+
+        let mut servers = vec![];
+        for server in raw_servers {
+            let missing = |key: &str| format!("missing field {} for server {}", key, server.id);
+
+            // Container is located by server's own selector properties if it has any, top-level ones otherwise
+            let server_selector = (
+                server.container_name.clone(),
+                server.container_id.clone(),
+                server.container_label.clone(),
+                server.compose_project.clone(),
+                server.compose_service.clone(),
+            );
+            let selector = if server_selector == (None, None, None, None, None) {
+                (
+                    raw.container_name.clone(),
+                    raw.container_id.clone(),
+                    raw.container_label.clone(),
+                    raw.compose_project.clone(),
+                    raw.compose_service.clone(),
+                )
+            } else {
+                server_selector
+            };
+            let container = match selector {
+                (Some(name), None, None, None, None) => ContainerSelector::Name(name),
+                (None, Some(id), None, None, None) => ContainerSelector::Id(id),
+                (None, None, Some(label), None, None) => ContainerSelector::Label(label),
+                (None, None, None, Some(project), Some(service)) => {
+                    ContainerSelector::Compose { project, service }
+                }
+                (None, None, None, None, None) => return Err(missing("container_name")),
+                _ => {
+                    return Err(format!(
+                        "server {} has to set exactly one of container_name, container_id, \
+                        container_label or compose_project together with compose_service",
+                        server.id
+                    ))
+                }
+            };
+
+            servers.push(ServerConfig {
+                name: server.name.clone().unwrap_or_else(|| server.id.clone()),
+                container,
+                valheim_server_address: server
+                    .valheim_server_address
+                    .unwrap_or(raw.valheim_server_address),
+                valheim_backups_path: server
+                    .valheim_backups_path
+                    .or_else(|| raw.valheim_backups_path.clone())
+                    .ok_or_else(|| missing("valheim_backups_path"))?,
+                valheim_backups_destination_path: server
+                    .valheim_backups_destination_path
+                    .or_else(|| raw.valheim_backups_destination_path.clone())
+                    .ok_or_else(|| missing("valheim_backups_destination_path"))?,
+                restart_delay_seconds: server
+                    .restart_delay_seconds
+                    .unwrap_or(raw.restart_delay_seconds),
+                backup_compression: server.backup_compression.unwrap_or(raw.backup_compression),
+                id: server.id,
+            });
+        }
+
+        Ok(Self {
+            server_address: raw.server_address,
+            docker_socket_path: raw.docker_socket_path,
+            docker_host: raw.docker_host,
+            docker_cert_path: raw.docker_cert_path,
+            docker_tls_verify: raw.docker_tls_verify,
+            template_path: raw.template_path,
+            last_log_lines_count: raw.last_log_lines_count,
+            data_path: raw.data_path,
+            username: raw.username,
+            password: raw.password,
+            users: raw.users.unwrap_or_default(),
+            servers,
+        })
+    }
 }
 
+/// Id of the server defined by top-level properties when `servers` list is not configured
+pub const DEFAULT_SERVER_ID: &str = "default";
+
+const ENV_PREFIX: &str = "VALMAN";
+
+const KNOWN_KEYS: &[&str] = &[
+    "server_address",
+    "docker_socket_path",
+    "docker_host",
+    "docker_cert_path",
+    "docker_tls_verify",
+    "container_name",
+    "container_id",
+    "container_label",
+    "compose_project",
+    "compose_service",
+    "template_path",
+    "valheim_server_address",
+    "valheim_backups_path",
+    "valheim_backups_destination_path",
+    "restart_delay_seconds",
+    "backup_compression",
+    "last_log_lines_count",
+    "data_path",
+    "username",
+    "password",
+    "users",
+    "servers",
+];
+
+const KNOWN_SERVER_KEYS: &[&str] = &[
+    "id",
+    "name",
+    "container_name",
+    "container_id",
+    "container_label",
+    "compose_project",
+    "compose_service",
+    "valheim_server_address",
+    "valheim_backups_path",
+    "valheim_backups_destination_path",
+    "restart_delay_seconds",
+    "backup_compression",
+];
+
+/// Old key names still accepted in place of the current ones, as `(deprecated, current)`
+const DEPRECATED_KEYS: &[(&str, &str)] = &[
+    (
+        "valheim_server_restart_delay_seconds",
+        "restart_delay_seconds",
+    ),
+    (
+        "valheim_server_last_log_lines_count",
+        "last_log_lines_count",
+    ),
+];
+
+/// Keys holding lists of tables, which can be set only in the config file
+const LIST_KEYS: &[&str] = &["servers", "users"];
+
 impl Config {
-    pub fn new() -> Result<Self, cfg::ConfigError> {
-        cfg::Config::builder()
+    /// Reads configuration from file at `path` (if present) and `VALMAN_*` environment variables,
+    /// environment takes precedence, except for [`LIST_KEYS`] settable only in the file. Problems that do not prevent loading are logged as warnings.
+    pub fn new(path: &Path) -> Result<Self, cfg::ConfigError> {
+        let (config, warnings) = Self::load(path)?;
+
+        for warning in warnings {
+            warn!("{}", warning);
+        }
+
+        Ok(config)
+    }
+
+    /// Same as [`Config::new`] but returns warnings (deprecated and unknown keys) instead of logging them
+    pub fn load(path: &Path) -> Result<(Self, Vec<String>), cfg::ConfigError> {
+        Self::load_from(path, cfg::Environment::with_prefix(ENV_PREFIX))
+    }
+
+    /// Reads configuration from file at `path` (if present) overridden by `env` source
+    fn load_from(
+        path: &Path,
+        env: cfg::Environment,
+    ) -> Result<(Self, Vec<String>), cfg::ConfigError> {
+        let mut warnings = vec![];
+
+        let file_keys: HashMap<String, cfg::Value> = cfg::Config::builder()
+            .add_source(cfg::File::from(path).required(false))
+            .build()?
+            .try_deserialize()?;
+        for key in file_keys.keys() {
+            let deprecated = DEPRECATED_KEYS.iter().any(|(old, _)| old == key);
+            if !deprecated && !KNOWN_KEYS.contains(&key.as_str()) {
+                warnings.push(format!("Unknown key {} in {}", key, path.display()));
+            }
+        }
+        if let Some(servers) = file_keys.get("servers") {
+            for (i, server) in servers.clone().into_array()?.into_iter().enumerate() {
+                for key in server.into_table()?.keys() {
+                    if !KNOWN_SERVER_KEYS.contains(&key.as_str()) {
+                        warnings.push(format!(
+                            "Unknown key servers[{}].{} in {}",
+                            i,
+                            key,
+                            path.display()
+                        ));
+                    }
+                }
+            }
+        }
+
+        let sources = cfg::Config::builder()
+            .add_source(cfg::File::from(path).required(false))
+            .add_source(env)
+            .build()?;
+        let provided_keys: HashMap<String, cfg::Value> = sources.clone().try_deserialize()?;
+        // Environment variables hold plain strings only, lists of tables cannot be made of them
+        for key in LIST_KEYS {
+            if let Some(value) = provided_keys.get(*key) {
+                if value.clone().into_array().is_err() {
+                    return Err(cfg::ConfigError::Message(format!(
+                        "{} has to be a list in the config file, it cannot be set from environment",
+                        key
+                    )));
+                }
+            }
+        }
+
+        let mut builder = cfg::Config::builder()
             .set_default("server_address", "0.0.0.0:9999")?
             .set_default("docker_socket_path", "/var/run/docker.sock")?
+            .set_default("docker_tls_verify", true)?
             .set_default("template_path", "templates/main.html")?
             .set_default("valheim_server_address", "127.0.0.1:2457")?
-            .set_default("valheim_server_restart_delay_seconds", 60)?
-            .set_default("valheim_server_last_log_lines_count", 100)?
-            .add_source(cfg::File::with_name("config.toml").required(true))
-            .build()?
-            .try_deserialize()
+            .set_default("restart_delay_seconds", 60)?
+            .set_default("backup_compression", "gzip")?
+            .set_default("last_log_lines_count", 100)?
+            .set_default("data_path", "data/")?
+            .add_source(sources);
+        for (old, new) in DEPRECATED_KEYS {
+            if let Some(value) = provided_keys.get(*old) {
+                warnings.push(format!("Key {} is deprecated, use {} instead", old, new));
+
+                // Value set with the current key name wins
+                if !provided_keys.contains_key(*new) {
+                    builder = builder.set_override(*new, value.clone())?;
+                }
+            }
+        }
+
+        let config: Self = builder.build()?.try_deserialize()?;
+        config.validate()?;
+
+        Ok((config, warnings))
+    }
+
+    fn validate(&self) -> Result<(), cfg::ConfigError> {
+        let invalid = |key: &str, reason: &str| {
+            Err(cfg::ConfigError::Message(format!(
+                "invalid value of {}: {}",
+                key, reason
+            )))
+        };
+
+        if self.servers.is_empty() {
+            return invalid("servers", "at least one server has to be configured");
+        }
+        for (i, server) in self.servers.iter().enumerate() {
+            if server.id.is_empty()
+                || !server
+                    .id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return invalid(
+                    "servers.id",
+                    "must be non-empty and contain only letters, digits, '-' and '_'",
+                );
+            }
+            if self.servers[..i].iter().any(|s| s.id == server.id) {
+                return invalid("servers.id", &format!("duplicate id {}", server.id));
+            }
+            let selector_empty = match &server.container {
+                ContainerSelector::Name(value)
+                | ContainerSelector::Id(value)
+                | ContainerSelector::Label(value) => value.trim().is_empty(),
+                ContainerSelector::Compose { project, service } => {
+                    project.trim().is_empty() || service.trim().is_empty()
+                }
+            };
+            if selector_empty {
+                return invalid(
+                    &format!("container selector of server {}", server.id),
+                    "must not be empty",
+                );
+            }
+        }
+        if let Some(docker_host) = &self.docker_host {
+            match docker_host.split_once("://") {
+                Some(("unix" | "tcp" | "http", _)) => {}
+                Some(("https", _)) if self.docker_cert_path.is_none() => {
+                    return invalid("docker_cert_path", "required for https:// Docker host");
+                }
+                Some(("https", _)) => {}
+                _ => {
+                    return invalid(
+                        "docker_host",
+                        "scheme has to be one of unix://, tcp://, http:// or https://",
+                    );
+                }
+            }
+        }
+        if self.username.is_empty() {
+            return invalid("username", "must not be empty");
+        }
+        if self.password.is_empty() {
+            return invalid("password", "must not be empty");
+        }
+        for (i, user) in self.users.iter().enumerate() {
+            if user.username.is_empty() || user.password.is_empty() {
+                return invalid("users", "username and password must not be empty");
+            }
+            if user.username.eq_ignore_ascii_case(&self.username)
+                || self.users[..i]
+                    .iter()
+                    .any(|u| u.username.eq_ignore_ascii_case(&user.username))
+            {
+                return invalid("users", &format!("duplicate username {}", user.username));
+            }
+        }
+        if self.last_log_lines_count == 0 {
+            return invalid("last_log_lines_count", "must be positive");
+        }
+
+        Ok(())
+    }
+
+    /// Role of the user with given credentials, `None` when they do not match any user
+    pub fn authenticate(&self, username: &str, password: &str) -> Option<UserRole> {
+        let matches = |expected_username: &str, expected_password: &str| {
+            username.eq_ignore_ascii_case(expected_username)
+                && constant_time_eq(password.as_bytes(), expected_password.as_bytes())
+        };
+
+        if matches(&self.username, &self.password) {
+            return Some(UserRole::Admin);
+        }
+
+        self.users
+            .iter()
+            .find(|user| matches(&user.username, &user.password))
+            .map(|user| user.role)
+    }
+
+    pub fn server(&self, id: &str) -> Option<&ServerConfig> {
+        self.servers.iter().find(|server| server.id == id)
+    }
+
+    /// Server used when none is selected explicitly
+    pub fn default_server(&self) -> &ServerConfig {
+        &self.servers[0]
+    }
+
+    /// Lists properties that differ from `other` but are only read at startup
+    pub fn restart_required_changes(&self, other: &Config) -> Vec<&'static str> {
+        let mut changes = vec![];
+
+        if self.server_address != other.server_address {
+            changes.push("server_address");
+        }
+        if self.docker_socket_path != other.docker_socket_path {
+            changes.push("docker_socket_path");
+        }
+        if self.docker_host != other.docker_host {
+            changes.push("docker_host");
+        }
+        if self.docker_cert_path != other.docker_cert_path
+            || self.docker_tls_verify != other.docker_tls_verify
+        {
+            changes.push("docker_cert_path");
+        }
+        if self.template_path != other.template_path {
+            changes.push("template_path");
+        }
+        if self.data_path != other.data_path {
+            changes.push("data_path");
+        }
+
+        changes
+    }
+}
+
+/// Compares two byte strings in time that does not depend on where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+impl Config {
+    /// Configuration made of `contents` of a config file only, the environment is not read
+    pub(crate) fn from_file_contents(contents: &str) -> Self {
+        let file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        std::fs::write(file.path(), contents).unwrap();
+
+        Self::load_from(
+            file.path(),
+            cfg::Environment::default().source(Some(HashMap::new())),
+        )
+        .unwrap()
+        .0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const CONFIG_FILE: &str = r#"
+container_name = "valheim"
+valheim_backups_path = "/srv/valheim/backups"
+valheim_backups_destination_path = "/srv/valheim/saves"
+username = "admin"
+password = "secret"
+"#;
+
+    /// Environment source made of `vars` only, so tests do not depend on the real environment
+    fn env(vars: &[(&str, &str)]) -> cfg::Environment {
+        let vars = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        cfg::Environment::with_prefix(ENV_PREFIX).source(Some(vars))
+    }
+
+    fn name(container_name: &str) -> ContainerSelector {
+        ContainerSelector::Name(container_name.to_string())
+    }
+
+    fn load(
+        contents: &str,
+        vars: &[(&str, &str)],
+    ) -> Result<(Config, Vec<String>), cfg::ConfigError> {
+        let file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        fs::write(file.path(), contents).unwrap();
+
+        Config::load_from(file.path(), env(vars))
+    }
+
+    #[test]
+    fn file_values_and_defaults_are_used() {
+        let (config, warnings) = load(CONFIG_FILE, &[]).unwrap();
+
+        assert_eq!(config.default_server().container, name("valheim"));
+        assert_eq!(config.username, "admin");
+        assert_eq!(config.server_address, "0.0.0.0:9999".parse().unwrap());
+        assert_eq!(config.default_server().restart_delay_seconds, 60);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn environment_overrides_file_values() {
+        let (config, _) = load(
+            CONFIG_FILE,
+            &[
+                ("VALMAN_CONTAINER_NAME", "valheim-test"),
+                ("VALMAN_PASSWORD", "from-env"),
+                ("VALMAN_RESTART_DELAY_SECONDS", "30"),
+                ("OTHER_PASSWORD", "ignored"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.default_server().container, name("valheim-test"));
+        assert_eq!(config.password, "from-env");
+        assert_eq!(config.default_server().restart_delay_seconds, 30);
+        assert_eq!(config.username, "admin");
+    }
+
+    #[test]
+    fn config_file_is_optional() {
+        let dir = tempfile::tempdir().unwrap();
+        let vars = [
+            ("VALMAN_CONTAINER_NAME", "valheim"),
+            ("VALMAN_VALHEIM_BACKUPS_PATH", "/srv/valheim/backups"),
+            (
+                "VALMAN_VALHEIM_BACKUPS_DESTINATION_PATH",
+                "/srv/valheim/saves",
+            ),
+            ("VALMAN_USERNAME", "admin"),
+            ("VALMAN_PASSWORD", "secret"),
+        ];
+
+        let (config, _) = Config::load_from(&dir.path().join("config.toml"), env(&vars)).unwrap();
+
+        assert_eq!(config.default_server().container, name("valheim"));
+        assert_eq!(config.password, "secret");
+    }
+
+    #[test]
+    fn lists_cannot_be_set_from_environment() {
+        let error = load(CONFIG_FILE, &[("VALMAN_SERVERS", "[]")]).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("servers has to be a list in the config file"));
+    }
+
+    #[test]
+    fn empty_values_are_rejected() {
+        let error = load(CONFIG_FILE, &[("VALMAN_PASSWORD", "")]).unwrap_err();
+
+        assert!(error.to_string().contains("password"));
+    }
+
+    #[test]
+    fn deprecated_key_is_used_when_current_one_is_missing() {
+        let contents = format!("{}valheim_server_restart_delay_seconds = 30\n", CONFIG_FILE);
+
+        let (config, warnings) = load(&contents, &[]).unwrap();
+
+        assert_eq!(config.default_server().restart_delay_seconds, 30);
+        assert_eq!(
+            warnings,
+            ["Key valheim_server_restart_delay_seconds is deprecated, use restart_delay_seconds instead"]
+        );
+    }
+
+    #[test]
+    fn current_key_wins_over_deprecated_one() {
+        let contents = format!(
+            "{}valheim_server_last_log_lines_count = 10\nlast_log_lines_count = 20\n",
+            CONFIG_FILE
+        );
+
+        let (config, warnings) = load(&contents, &[]).unwrap();
+
+        assert_eq!(config.last_log_lines_count, 20);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn current_key_from_environment_wins_over_deprecated_one_from_file() {
+        let contents = format!("{}valheim_server_restart_delay_seconds = 30\n", CONFIG_FILE);
+
+        let (config, warnings) =
+            load(&contents, &[("VALMAN_RESTART_DELAY_SECONDS", "45")]).unwrap();
+
+        assert_eq!(config.default_server().restart_delay_seconds, 45);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn deprecated_key_is_accepted_from_environment() {
+        let (config, warnings) = load(
+            CONFIG_FILE,
+            &[("VALMAN_VALHEIM_SERVER_LAST_LOG_LINES_COUNT", "10")],
+        )
+        .unwrap();
+
+        assert_eq!(config.last_log_lines_count, 10);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let contents = format!("{}contianer_name = \"valheim\"\n", CONFIG_FILE);
+
+        let (config, warnings) = load(&contents, &[]).unwrap();
+
+        assert_eq!(config.default_server().container, name("valheim"));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Unknown key contianer_name in "));
+    }
+
+    #[test]
+    fn top_level_properties_define_default_server() {
+        let (config, _) = load(CONFIG_FILE, &[]).unwrap();
+
+        assert_eq!(config.servers.len(), 1);
+        let server = config.default_server();
+        assert_eq!(server.id, DEFAULT_SERVER_ID);
+        assert_eq!(server.name, DEFAULT_SERVER_ID);
+        assert_eq!(
+            server.valheim_backups_path,
+            Path::new("/srv/valheim/backups")
+        );
+    }
+
+    #[test]
+    fn servers_fall_back_to_top_level_properties() {
+        let contents = format!(
+            r#"{}
+restart_delay_seconds = 120
+
+[[servers]]
+id = "main"
+name = "Main world"
+
+[[servers]]
+id = "hardcore"
+compose_project = "valheim"
+compose_service = "hardcore"
+restart_delay_seconds = 30
+valheim_backups_path = "/srv/hardcore/backups"
+"#,
+            CONFIG_FILE
+        );
+
+        let (config, warnings) = load(&contents, &[]).unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(config.default_server().id, "main");
+        let main = config.server("main").unwrap();
+        assert_eq!(main.name, "Main world");
+        assert_eq!(main.container, name("valheim"));
+        assert_eq!(main.restart_delay_seconds, 120);
+        assert_eq!(main.valheim_backups_path, Path::new("/srv/valheim/backups"));
+
+        let hardcore = config.server("hardcore").unwrap();
+        assert_eq!(hardcore.name, "hardcore");
+        assert_eq!(
+            hardcore.container,
+            ContainerSelector::Compose {
+                project: "valheim".to_string(),
+                service: "hardcore".to_string(),
+            }
+        );
+        assert_eq!(hardcore.restart_delay_seconds, 30);
+        assert_eq!(
+            hardcore.valheim_backups_path,
+            Path::new("/srv/hardcore/backups")
+        );
+        assert_eq!(
+            hardcore.valheim_backups_destination_path,
+            Path::new("/srv/valheim/saves")
+        );
+        assert!(config.server("other").is_none());
+    }
+
+    #[test]
+    fn server_without_container_is_rejected() {
+        let contents = r#"
+username = "admin"
+password = "secret"
+valheim_backups_path = "/srv/valheim/backups"
+valheim_backups_destination_path = "/srv/valheim/saves"
+
+[[servers]]
+id = "main"
+container_name = "valheim"
+
+[[servers]]
+id = "hardcore"
+"#;
+
+        let error = load(contents, &[]).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("missing field container_name for server hardcore"));
+    }
+
+    #[test]
+    fn invalid_server_ids_are_rejected() {
+        let duplicate = format!(
+            "{}\n[[servers]]\nid = \"main\"\n\n[[servers]]\nid = \"main\"\n",
+            CONFIG_FILE
+        );
+        let error = load(&duplicate, &[]).unwrap_err();
+        assert!(error.to_string().contains("duplicate id main"));
+
+        let invalid = format!("{}\n[[servers]]\nid = \"main/world\"\n", CONFIG_FILE);
+        assert!(load(&invalid, &[]).is_err());
+    }
+
+    #[test]
+    fn unknown_server_keys_are_reported() {
+        let contents = format!(
+            "{}\n[[servers]]\nid = \"main\"\nrestart_delay = 30\n",
+            CONFIG_FILE
+        );
+
+        let (_, warnings) = load(&contents, &[]).unwrap();
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Unknown key servers[0].restart_delay in "));
+    }
+
+    #[test]
+    fn server_selector_replaces_top_level_one() {
+        // Merged with the top-level container_name, the id would make the selector ambiguous
+        let contents = format!(
+            "{}\n[[servers]]\nid = \"main\"\ncontainer_id = \"0123456789ab\"\n",
+            CONFIG_FILE
+        );
+
+        let (config, _) = load(&contents, &[]).unwrap();
+
+        assert_eq!(
+            config.default_server().container,
+            ContainerSelector::Id("0123456789ab".to_string())
+        );
+    }
+
+    #[test]
+    fn ambiguous_or_incomplete_selector_is_rejected() {
+        let both = format!("{}container_id = \"0123456789ab\"\n", CONFIG_FILE);
+        let error = load(&both, &[]).unwrap_err();
+        assert!(error.to_string().contains("exactly one of"));
+
+        let incomplete_compose = format!("{}compose_project = \"valheim\"\n", CONFIG_FILE);
+        let error = load(&incomplete_compose, &[]).unwrap_err();
+        assert!(error.to_string().contains("exactly one of"));
+
+        let error = load(CONFIG_FILE, &[("VALMAN_CONTAINER_LABEL", "valheim")]).unwrap_err();
+        assert!(error.to_string().contains("exactly one of"));
+    }
+
+    #[test]
+    fn empty_selector_is_rejected() {
+        let contents =
+            CONFIG_FILE.replace("container_name = \"valheim\"", "container_label = \" \"");
+
+        let error = load(&contents, &[]).unwrap_err();
+
+        assert!(error.to_string().contains("must not be empty"));
     }
 }
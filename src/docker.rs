@@ -1,54 +1,254 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
 
-use crate::error::{
-    Result,
-    ValmanError::{Docker as DockerError, DockerApi},
+use crate::{
+    config::{Config, ContainerSelector},
+    error::{
+        Result,
+        ValmanError::{ContainerNotFound, Docker as DockerError, DockerApi},
+    },
+};
+use chrono::{DateTime, Local, NaiveDateTime};
+use docker_api::{
+    models::{
+        ContainerConfig, EndpointSettings, EventMessage, ImageBuildChunk, Labels, MountPoint,
+    },
+    opts::{
+        ContainerConnectionOpts, ContainerCreateOpts, ContainerFilter, ContainerListOpts,
+        ContainerRemoveOpts, EventFilter, EventFilterType, EventsOpts, ExecCreateOpts, LogsOpts,
+        PublishPort, PullOpts,
+    },
+    Container, Docker, Exec,
 };
-use docker_api::{opts::LogsOpts, Docker};
-use futures_util::StreamExt;
-use log::{debug, error};
+use futures_util::{Stream, StreamExt};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Container events tracked by valman, `health_status` events carry the new status in their action
+const WATCHED_EVENTS: [&str; 5] = ["start", "die", "oom", "health_status", "restart"];
+/// Host config properties carried over when the container is recreated, `Mounts` are converted
+/// to binds
+const RECREATED_HOST_CONFIG: [&str; 18] = [
+    "Binds",
+    "Mounts",
+    "NetworkMode",
+    "RestartPolicy",
+    "PortBindings",
+    "PublishAllPorts",
+    "ExtraHosts",
+    "CapAdd",
+    "SecurityOpt",
+    "VolumesFrom",
+    "Devices",
+    "LogConfig",
+    "Memory",
+    "MemorySwap",
+    "NanoCpus",
+    "CpuShares",
+    "UsernsMode",
+    "Privileged",
+];
+/// Size of `/dev/shm` Docker gives containers by default
+const DEFAULT_SHM_SIZE: u64 = 64 * 1024 * 1024;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ContainerInfo {
-    pub id: String,
-    pub state: String,
+    #[serde(flatten)]
+    pub status: ContainerStatus,
     pub uptime: String,
     pub logs: String,
 }
 
 impl ContainerInfo {
-    pub fn new(id: String, state: String, uptime: String, logs: String) -> Self {
+    pub fn new(status: ContainerStatus, logs: String) -> Self {
         Self {
-            id,
-            state,
-            uptime,
+            uptime: status.uptime(),
+            status,
             logs,
         }
     }
 }
 
-async fn find_container_id_and_state_by_name(
+/// Last known state of a container, kept up to date by Docker events
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContainerStatus {
+    pub id: String,
+    pub state: String,
+    pub health: Option<String>,
+    pub exit_code: Option<i64>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    /// Time the container was last killed for running out of memory
+    pub oom_killed_at: Option<NaiveDateTime>,
+}
+
+impl ContainerStatus {
+    /// Human readable uptime, or time since the container stopped
+    pub fn uptime(&self) -> String {
+        let now = Local::now().naive_local();
+
+        match (self.state.as_str(), self.started_at, self.finished_at) {
+            ("running" | "restarting", Some(started_at), _) => {
+                format!("Up {}", format_duration(now - started_at))
+            }
+            (_, _, Some(finished_at)) => format!(
+                "Exited ({}) {} ago",
+                self.exit_code
+                    .map(|code| code.to_string())
+                    .unwrap_or_else(|| "?".to_string()),
+                format_duration(now - finished_at)
+            ),
+            _ => self.state.clone(),
+        }
+    }
+
+    /// Applies container event to the status, returns `false` for events not affecting it
+    pub fn apply_event(
+        &mut self,
+        action: &str,
+        attributes: &HashMap<String, String>,
+        time: NaiveDateTime,
+    ) -> bool {
+        match action.split_once(": ") {
+            Some(("health_status", health)) => self.health = Some(health.to_string()),
+            _ => match action {
+                "start" | "restart" => {
+                    self.state = "running".to_string();
+                    self.started_at = Some(time);
+                    self.exit_code = None;
+                }
+                "die" => {
+                    self.state = "exited".to_string();
+                    self.finished_at = Some(time);
+                    self.exit_code = attributes
+                        .get("exitCode")
+                        .and_then(|code| code.parse().ok());
+                    self.health = None;
+                }
+                "oom" => self.oom_killed_at = Some(time),
+                _ => return false,
+            },
+        }
+
+        true
+    }
+}
+
+/// Creates Docker client for endpoint chosen by `docker_host` URL scheme, `docker_socket_path`
+/// Unix socket is used when `docker_host` is not set
+pub fn connect(config: &Config) -> Result<Docker> {
+    let Some(docker_host) = &config.docker_host else {
+        return Ok(Docker::unix(&config.docker_socket_path));
+    };
+
+    debug!("Connecting to Docker host {}", docker_host);
+
+    match docker_host.split_once("://") {
+        Some(("unix", socket_path)) => Ok(Docker::unix(socket_path)),
+        Some(("tcp" | "http", authority)) => Ok(Docker::tcp(authority)?),
+        Some(("https", authority)) => {
+            let cert_path = config.docker_cert_path.as_ref().ok_or_else(|| {
+                DockerError("docker_cert_path is required for https:// Docker host".to_string())
+            })?;
+
+            Ok(Docker::tls(authority, cert_path, config.docker_tls_verify)?)
+        }
+        _ => Err(DockerError(format!(
+            "Unsupported Docker host {}",
+            docker_host
+        ))),
+    }
+}
+
+/// Image a container runs compared with the newest image available under the same reference
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageStatus {
+    /// Image reference the container was created from, e.g. `mbround18/valheim:latest`
+    pub image: String,
+    pub update_available: bool,
+    /// Why the registry could not be checked, availability is then based on local images only
+    pub registry_error: Option<String>,
+    pub checked_at: NaiveDateTime,
+}
+
+/// Output of a command executed inside a container
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecOutput {
+    pub command: String,
+    /// Combined stdout and stderr
+    pub output: String,
+    pub exit_code: Option<i64>,
+    pub time: NaiveDateTime,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+
+/// Finds container matching `selector`, stopped containers included. When several containers match,
+/// a running one is preferred.
+async fn find_container_id_and_state(
     docker: &Docker,
-    name: &str,
+    selector: &ContainerSelector,
 ) -> Result<(String, String, String)> {
-    let container = match docker.containers().list(&Default::default()).await {
-        Ok(containers) => {
-            let cont = containers
-                .into_iter()
-                .find(|c| {
-                    c.names
-                        .as_ref()
-                        .unwrap()
-                        .first()
-                        .unwrap()
-                        .eq_ignore_ascii_case(&format!("/{}", name))
-                })
-                .ok_or_else(|| DockerError("Missing Docker container".to_string()))?;
+    let filters = match selector {
+        // Docker matches names by substring, exact match is checked below
+        ContainerSelector::Name(name) => vec![ContainerFilter::Name(name.clone())],
+        ContainerSelector::Id(id) => vec![ContainerFilter::Id(id.clone())],
+        ContainerSelector::Label(label) => match label.split_once('=') {
+            Some((key, value)) => vec![ContainerFilter::Label(key.to_string(), value.to_string())],
+            None => vec![ContainerFilter::LabelKey(label.clone())],
+        },
+        ContainerSelector::Compose { project, service } => vec![
+            ContainerFilter::Label(COMPOSE_PROJECT_LABEL.to_string(), project.clone()),
+            ContainerFilter::Label(COMPOSE_SERVICE_LABEL.to_string(), service.clone()),
+        ],
+    };
 
-            Ok(cont)
-        }
-        Err(e) => Err(DockerApi(e)),
-    }?;
+    let containers = docker
+        .containers()
+        .list(
+            &ContainerListOpts::builder()
+                .all(true)
+                .filter(filters)
+                .build(),
+        )
+        .await
+        .map_err(DockerApi)?;
+    let mut matching: Vec<_> = containers
+        .into_iter()
+        .filter(|c| match selector {
+            ContainerSelector::Name(name) => c
+                .names
+                .iter()
+                .flatten()
+                .any(|n| n.trim_start_matches('/').eq_ignore_ascii_case(name)),
+            _ => true,
+        })
+        .collect();
+    if matching.is_empty() {
+        return Err(ContainerNotFound(selector.to_string()));
+    }
+    if matching.len() > 1 {
+        warn!(
+            "{} containers match {}, using the first running one",
+            matching.len(),
+            selector
+        );
+    }
+    let running_idx = matching
+        .iter()
+        .position(|c| c.state.as_deref() == Some("running"))
+        .unwrap_or(0);
+    let container = matching.swap_remove(running_idx);
 
     let id = container
         .id
@@ -63,21 +263,129 @@ async fn find_container_id_and_state_by_name(
     Ok((id, state, uptime))
 }
 
-pub async fn retrieve_container_info(
+/// Checks whether container described by Docker event actor is the one selected by `selector`
+pub fn selector_matches(
+    selector: &ContainerSelector,
+    id: &str,
+    attributes: &HashMap<String, String>,
+) -> bool {
+    let label_matches =
+        |key: &str, value: &str| attributes.get(key).map(String::as_str) == Some(value);
+
+    match selector {
+        ContainerSelector::Name(name) => attributes
+            .get("name")
+            .is_some_and(|n| n.eq_ignore_ascii_case(name)),
+        ContainerSelector::Id(selector_id) => id.starts_with(selector_id.as_str()),
+        ContainerSelector::Label(label) => match label.split_once('=') {
+            Some((key, value)) => label_matches(key, value),
+            None => attributes.contains_key(label),
+        },
+        ContainerSelector::Compose { project, service } => {
+            label_matches(COMPOSE_PROJECT_LABEL, project)
+                && label_matches(COMPOSE_SERVICE_LABEL, service)
+        }
+    }
+}
+
+pub async fn retrieve_container_status(
     docker: &Docker,
-    container_name: &str,
-    last_n_lines: usize,
-) -> Result<ContainerInfo> {
-    let (container_id, container_state, container_uptime) =
-        find_container_id_and_state_by_name(docker, container_name).await?;
-    let container = docker.containers().get(&container_id);
+    container: &ContainerSelector,
+) -> Result<ContainerStatus> {
+    let (container_id, container_state, _) = find_container_id_and_state(docker, container).await?;
+
+    debug!(
+        "Inspecting container {} with id {}",
+        container, container_id
+    );
+
+    let inspect = docker.containers().get(&container_id).inspect().await?;
+    let state = inspect
+        .state
+        .ok_or_else(|| DockerError("Missing Docker container state".to_string()))?;
+    let finished_at = state.finished_at.as_deref().and_then(parse_docker_time);
+
+    Ok(ContainerStatus {
+        id: container_id,
+        state: state.status.unwrap_or(container_state),
+        health: state.health.and_then(|health| health.status),
+        exit_code: state.exit_code.map(|code| code as i64),
+        started_at: state.started_at.as_deref().and_then(parse_docker_time),
+        finished_at,
+        oom_killed_at: state
+            .oom_killed
+            .unwrap_or_default()
+            .then_some(finished_at)
+            .flatten(),
+    })
+}
+
+/// Retrieves environment variables of the container as `KEY=value` entries, image defaults included
+pub async fn retrieve_container_env(
+    docker: &Docker,
+    container: &ContainerSelector,
+) -> Result<Vec<String>> {
+    let (container_id, _, _) = find_container_id_and_state(docker, container).await?;
+
+    Ok(docker
+        .containers()
+        .get(&container_id)
+        .inspect()
+        .await?
+        .config
+        .and_then(|config| config.env)
+        .unwrap_or_default())
+}
+
+/// Runs `command` inside the running container and waits for it to finish, capturing its output
+pub async fn exec_in_container(
+    docker: &Docker,
+    container: &ContainerSelector,
+    command: &[String],
+) -> Result<ExecOutput> {
+    let (container_id, _, _) = find_container_id_and_state(docker, container).await?;
 
     debug!(
-        "Retrieving container info for name {} and id {}",
-        container_name, container_id
+        "Executing {:?} in container {} with id {}",
+        command, container, container_id
     );
 
-    let cont_logs = container.logs(
+    let exec = Exec::create(
+        docker.clone(),
+        &container_id,
+        &ExecCreateOpts::builder()
+            .command(command)
+            .attach_stdout(true)
+            .attach_stderr(true)
+            .build(),
+    )
+    .await?;
+    let mut output = vec![];
+    let mut chunks = exec.start();
+    while let Some(chunk) = chunks.next().await {
+        output.extend(Vec::from(chunk.map_err(docker_api::Error::from)?));
+    }
+    let exit_code = exec.inspect().await?.exit_code.map(|code| code as i64);
+
+    Ok(ExecOutput {
+        command: command.join(" "),
+        output: String::from_utf8_lossy(&output).to_string(),
+        exit_code,
+        time: Local::now().naive_local(),
+    })
+}
+
+/// Retrieves last `last_n_lines` lines of container stdout, read errors are logged and skipped
+pub async fn retrieve_container_logs(
+    docker: &Docker,
+    container_id: &str,
+    last_n_lines: usize,
+) -> String {
+    let container_handle = docker.containers().get(container_id);
+
+    debug!("Retrieving logs of container {}", container_id);
+
+    let cont_logs = container_handle.logs(
         &LogsOpts::builder()
             .stdout(true)
             .n_lines(last_n_lines)
@@ -97,26 +405,927 @@ pub async fn retrieve_container_info(
         .flatten()
         .collect::<Vec<_>>();
 
-    let logs = String::from_utf8_lossy(&logs);
+    String::from_utf8_lossy(&logs).to_string()
+}
 
-    Ok(ContainerInfo::new(
-        container_id,
-        container_state,
-        container_uptime,
-        logs.to_string(),
-    ))
+/// Subscribes to [`WATCHED_EVENTS`] of all containers, matching managed containers is up to the caller
+pub fn container_events(docker: &Docker) -> impl Stream<Item = Result<EventMessage>> + Unpin + '_ {
+    let mut filters = vec![EventFilter::Type(EventFilterType::Container)];
+    filters.extend(
+        WATCHED_EVENTS
+            .iter()
+            .map(|event| EventFilter::Event(event.to_string())),
+    );
+
+    docker
+        .events(&EventsOpts::builder().filter(filters).build())
+        .map(|event| event.map_err(DockerApi))
 }
 
-pub async fn restart_container(docker: &Docker, container_name: &str) -> Result<()> {
-    let (container_id, _, _) = find_container_id_and_state_by_name(docker, container_name).await?;
-    let container = docker.containers().get(&container_id);
+/// Parses Docker RFC 3339 timestamp to local time, Docker reports unset times as year 1
+pub fn parse_docker_time(time: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .filter(|time| time.timestamp() > 0)
+        .map(|time| time.with_timezone(&Local).naive_local())
+}
+
+fn format_duration(duration: chrono::Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+
+    match minutes {
+        0 => "less than a minute".to_string(),
+        1..=59 => format!("{} minutes", minutes),
+        60..=1439 => format!("{} hours {} minutes", minutes / 60, minutes % 60),
+        _ => format!("{} days {} hours", minutes / 1440, minutes % 1440 / 60),
+    }
+}
+
+pub async fn restart_container(docker: &Docker, container: &ContainerSelector) -> Result<()> {
+    let (container_id, _, _) = find_container_id_and_state(docker, container).await?;
+    let container_handle = docker.containers().get(&container_id);
 
     debug!(
         "Restarting container {} with id {}",
-        container_name, container_id
+        container, container_id
+    );
+
+    container_handle
+        .restart(Some(Duration::from_secs(10)))
+        .await?;
+
+    Ok(())
+}
+
+/// Streams container logs to `on_chunk`, starting with the last `last_n_lines` lines.
+/// With `follow` enabled it keeps waiting for new output until the container stops.
+pub async fn stream_container_logs<F>(
+    docker: &Docker,
+    container: &ContainerSelector,
+    last_n_lines: usize,
+    follow: bool,
+    mut on_chunk: F,
+) -> Result<()>
+where
+    F: FnMut(&[u8]),
+{
+    let (container_id, _, _) = find_container_id_and_state(docker, container).await?;
+    let container_handle = docker.containers().get(&container_id);
+
+    debug!(
+        "Streaming logs of container {} with id {}",
+        container, container_id
+    );
+
+    let mut logs = container_handle.logs(
+        &LogsOpts::builder()
+            .stdout(true)
+            .stderr(true)
+            .n_lines(last_n_lines)
+            .follow(follow)
+            .build(),
+    );
+    while let Some(chunk) = logs.next().await {
+        on_chunk(&chunk?);
+    }
+
+    Ok(())
+}
+
+/// Checks whether the image reference of the container points to a newer image than the one it runs,
+/// either in the registry or because a newer image was already pulled locally
+pub async fn check_image_update(
+    docker: &Docker,
+    container: &ContainerSelector,
+) -> Result<ImageStatus> {
+    let (container_id, _, _) = find_container_id_and_state(docker, container).await?;
+    let inspect = docker.containers().get(&container_id).inspect().await?;
+    let image = inspect
+        .config
+        .and_then(|config| config.image)
+        .ok_or_else(|| DockerError("Missing Docker container image".to_string()))?;
+    let container_image_id = inspect
+        .image
+        .ok_or_else(|| DockerError("Missing Docker container image ID".to_string()))?;
+
+    debug!(
+        "Checking image {} of container {} for updates",
+        image, container
+    );
+
+    let images = docker.images();
+    let local_image_id = images.get(&image).inspect().await?.id;
+    let mut update_available = local_image_id.as_deref() != Some(container_image_id.as_str());
+
+    // Registry reports manifest digest, which is listed in repo digests of the pulled image
+    let mut registry_error = None;
+    match images.get(&image).distribution_inspect().await {
+        Ok(distribution) => {
+            if let Some(digest) = distribution.descriptor.digest {
+                let repo_digests = images
+                    .get(&container_image_id)
+                    .inspect()
+                    .await?
+                    .repo_digests
+                    .unwrap_or_default();
+
+                update_available |= !repo_digests
+                    .iter()
+                    .any(|repo_digest| repo_digest.ends_with(&format!("@{}", digest)));
+            }
+        }
+        Err(e) => {
+            warn!("Failed checking image {} in registry: {}", image, e);
+
+            registry_error = Some(e.to_string());
+        }
+    }
+
+    Ok(ImageStatus {
+        image,
+        update_available,
+        registry_error,
+        checked_at: Local::now().naive_local(),
+    })
+}
+
+/// Pulls the newest image of the container image reference and recreates the container from it
+pub async fn update_container(
+    docker: &Docker,
+    container: RecreatableContainer<'_>,
+) -> Result<String> {
+    let (container_id, _, _) = find_container_id_and_state(docker, container.0).await?;
+    let image = docker
+        .containers()
+        .get(&container_id)
+        .inspect()
+        .await?
+        .config
+        .and_then(|config| config.image)
+        .ok_or_else(|| DockerError("Missing Docker container image".to_string()))?;
+
+    pull_image(docker, &image).await?;
+
+    recreate_container(docker, container, &BTreeMap::new()).await
+}
+
+/// Container selector checked by [`ensure_recreatable`], required by functions recreating the container
+#[derive(Debug, Clone, Copy)]
+pub struct RecreatableContainer<'a>(&'a ContainerSelector);
+
+/// Recreated container gets a new id, so a container selected by id could not be found afterwards.
+/// Callers check this before anything else (e.g. taking a backup) is done.
+pub fn ensure_recreatable(container: &ContainerSelector) -> Result<RecreatableContainer<'_>> {
+    match container {
+        ContainerSelector::Id(id) => Err(DockerError(format!(
+            "Container selected by id {} cannot be recreated as the new container gets a different \
+             id, select it by container_name, container_label or compose service instead",
+            id
+        ))),
+        _ => Ok(RecreatableContainer(container)),
+    }
+}
+
+async fn pull_image(docker: &Docker, image: &str) -> Result<()> {
+    let (repository, tag) = split_image_reference(image);
+
+    debug!("Pulling image {}:{}", repository, tag);
+
+    let images = docker.images();
+    let mut pull = images.pull(&PullOpts::builder().image(repository).tag(tag).build());
+    while let Some(chunk) = pull.next().await {
+        match chunk? {
+            ImageBuildChunk::Error { error, .. } => {
+                return Err(DockerError(format!(
+                    "Failed pulling image {}: {}",
+                    image, error
+                )))
+            }
+            ImageBuildChunk::PullStatus { status, .. } => debug!("{}: {}", image, status),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits image reference to repository and tag, or digest when the reference is pinned to one.
+/// Docker accepts digest in place of the tag when pulling.
+fn split_image_reference(image: &str) -> (&str, &str) {
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+    // Tag separator is the last colon not belonging to registry host port
+    let (repository, tag) = match name.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+        _ => (name, "latest"),
+    };
+
+    (repository, digest.unwrap_or(tag))
+}
+
+/// Replaces the container with a new one created from the same configuration and image reference,
+/// so it picks up a newly pulled image. Values inherited from the old image are not copied, allowing
+/// the new image to change them. `env_changes` replace or add environment variables.
+/// Previous container is kept until the new one starts and is brought back when that fails.
+/// Containers with settings that cannot be carried over are refused before anything is changed.
+pub async fn recreate_container(
+    docker: &Docker,
+    container: RecreatableContainer<'_>,
+    env_changes: &BTreeMap<String, String>,
+) -> Result<String> {
+    let (container_id, container_state, _) =
+        find_container_id_and_state(docker, container.0).await?;
+    let container_handle = docker.containers().get(&container_id);
+    let inspect = container_handle.inspect().await?;
+
+    let name = inspect
+        .name
+        .as_deref()
+        .map(|name| name.trim_start_matches('/').to_string())
+        .ok_or_else(|| DockerError("Missing Docker container name".to_string()))?;
+    let config = inspect
+        .config
+        .ok_or_else(|| DockerError("Missing Docker container config".to_string()))?;
+    let image = config
+        .image
+        .clone()
+        .ok_or_else(|| DockerError("Missing Docker container image".to_string()))?;
+    let image_config = match &inspect.image {
+        Some(image_id) => docker.images().get(image_id).inspect().await?.config,
+        None => None,
+    }
+    .unwrap_or_else(|| ContainerConfig {
+        image: None,
+        ..config.clone()
+    });
+    let host_config = inspect.host_config.unwrap_or_default();
+    let mounts = inspect.mounts.unwrap_or_default();
+
+    ensure_carried_over(
+        &name,
+        &container_id,
+        &config,
+        &image_config,
+        &host_config,
+        &mounts,
+    )?;
+
+    let networks = inspect
+        .network_settings
+        .and_then(|settings| settings.networks)
+        .unwrap_or_default();
+    let network_mode = host_config["NetworkMode"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let opts = create_opts(
+        &name,
+        &image,
+        config,
+        image_config,
+        &host_config,
+        &mounts,
+        env_changes,
     );
 
-    container.restart(Some(Duration::from_secs(10))).await?;
+    info!(
+        "Recreating container {} ({}) from image {}",
+        name, container_id, image
+    );
+
+    let was_running = container_state == "running";
+    if was_running {
+        container_handle.stop(Some(Duration::from_secs(30))).await?;
+    }
+    let old_name = format!("{}-valman-old", name);
+    container_handle.rename(&old_name).await?;
+
+    let new_container =
+        create_new_container(docker, &container_handle, &opts, &name, was_running).await?;
+    if let Err(e) = start_new_container(docker, &new_container, networks, &network_mode).await {
+        error!(
+            "Failed starting container {}, restoring previous one: {}",
+            name, e
+        );
+        restore_previous_container(&container_handle, Some(&new_container), &name, was_running)
+            .await?;
+
+        return Err(e);
+    }
+
+    if let Err(e) = container_handle.delete().await {
+        warn!("Failed removing previous container {}: {}", old_name, e);
+    }
+
+    Ok(new_container.id().to_string())
+}
+
+/// Refuses containers with settings that would not be carried over to the recreated container
+fn ensure_carried_over(
+    name: &str,
+    container_id: &str,
+    config: &ContainerConfig,
+    image_config: &ContainerConfig,
+    host_config: &Value,
+    mounts: &[MountPoint],
+) -> Result<()> {
+    let mut unsupported = unsupported_config(config, image_config, container_id);
+    unsupported.extend(unsupported_host_config(host_config));
+    for mount in mounts {
+        if !matches!(mount.type_.as_deref(), Some("bind" | "volume")) {
+            unsupported.push(format!(
+                "Mounts ({} at {})",
+                mount.type_.as_deref().unwrap_or("unknown"),
+                mount.destination.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+    if !unsupported.is_empty() {
+        return Err(DockerError(format!(
+            "Container {} cannot be recreated by valman, it has settings that would not be carried \
+             over: {}",
+            name,
+            unsupported.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Options creating a copy of the container, with `env_changes` applied to its environment.
+/// Values equal to the ones of the image are left out, so they follow the image.
+fn create_opts(
+    name: &str,
+    image: &str,
+    config: ContainerConfig,
+    image_config: ContainerConfig,
+    host_config: &Value,
+    mounts: &[MountPoint],
+    env_changes: &BTreeMap<String, String>,
+) -> ContainerCreateOpts {
+    let image_env = image_config.env.unwrap_or_default();
+    let mut env: Vec<_> = config
+        .env
+        .unwrap_or_default()
+        .into_iter()
+        .map(|var| match var.split_once('=') {
+            Some((key, _)) if env_changes.contains_key(key) => {
+                format!("{}={}", key, env_changes[key])
+            }
+            _ => var,
+        })
+        .collect();
+    for (key, value) in env_changes {
+        if !env
+            .iter()
+            .any(|var| var.split_once('=').map(|(k, _)| k) == Some(key))
+        {
+            env.push(format!("{}={}", key, value));
+        }
+    }
+    let image_labels = image_config.labels.unwrap_or_default();
+    let mut opts = ContainerCreateOpts::builder()
+        .name(name)
+        .image(image)
+        .env(env.into_iter().filter(|var| !image_env.contains(var)))
+        .labels(
+            config
+                .labels
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(key, value)| image_labels.get(key) != Some(value)),
+        )
+        .volumes(mounts.iter().filter_map(mount_bind))
+        .tty(config.tty.unwrap_or_default());
+    if config.cmd.is_some() && config.cmd != image_config.cmd {
+        opts = opts.command(config.cmd.unwrap_or_default());
+    }
+    if config.entrypoint.is_some() && config.entrypoint != image_config.entrypoint {
+        opts = opts.entrypoint(config.entrypoint.unwrap_or_default());
+    }
+    if let Some(user) = config
+        .user
+        .filter(|user| Some(user) != image_config.user.as_ref())
+    {
+        opts = opts.user(user);
+    }
+    if let Some(working_dir) = config
+        .working_dir
+        .filter(|dir| !dir.is_empty() && Some(dir) != image_config.working_dir.as_ref())
+    {
+        opts = opts.working_dir(working_dir);
+    }
+    if let Some(stop_signal) = config
+        .stop_signal
+        .filter(|signal| Some(signal) != image_config.stop_signal.as_ref())
+    {
+        opts = opts.stop_signal(stop_signal);
+    }
+    if config.open_stdin == Some(true) {
+        opts = opts.attach_stdin(true);
+    }
+    if let Some(network_mode) = host_config["NetworkMode"].as_str() {
+        opts = opts.network_mode(network_mode);
+    }
+    if let Some(restart_policy) = host_config["RestartPolicy"]["Name"].as_str() {
+        if !restart_policy.is_empty() {
+            opts = opts.restart_policy(
+                restart_policy,
+                host_config["RestartPolicy"]["MaximumRetryCount"]
+                    .as_u64()
+                    .unwrap_or_default(),
+            );
+        }
+    }
+    let mut bound_ports = vec![];
+    if let Some(port_bindings) = host_config["PortBindings"].as_object() {
+        for (container_port, bindings) in port_bindings {
+            let Ok(publish_port) = port_with_protocol(container_port).parse::<PublishPort>() else {
+                continue;
+            };
+
+            for host_port in bindings
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|binding| binding["HostPort"].as_str()?.parse().ok())
+            {
+                opts = opts.expose(publish_port.clone(), host_port);
+            }
+            bound_ports.push(container_port.as_str());
+        }
+    }
+    // Exposing ports replaces exposed ports list, ones without binding are published afterwards
+    for exposed_port in config.exposed_ports.iter().flat_map(|ports| ports.keys()) {
+        let from_image = image_config
+            .exposed_ports
+            .as_ref()
+            .is_some_and(|ports| ports.contains_key(exposed_port));
+        if from_image || bound_ports.contains(&exposed_port.as_str()) {
+            continue;
+        }
+        if let Ok(publish_port) = port_with_protocol(exposed_port).parse::<PublishPort>() {
+            opts = opts.publish(publish_port);
+        }
+    }
+    if host_config["PublishAllPorts"].as_bool() == Some(true) {
+        opts = opts.publish_all_ports();
+    }
+    if let Some(extra_hosts) = host_config["ExtraHosts"].as_array() {
+        opts = opts.extra_hosts(extra_hosts.iter().filter_map(|host| host.as_str()));
+    }
+    if let Some(cap_add) = host_config["CapAdd"].as_array() {
+        opts = opts.capabilities(cap_add.iter().filter_map(|cap| cap.as_str()));
+    }
+    if let Some(security_opt) = host_config["SecurityOpt"].as_array() {
+        opts = opts.security_options(security_opt.iter().filter_map(|opt| opt.as_str()));
+    }
+    if let Some(volumes_from) = host_config["VolumesFrom"].as_array() {
+        opts = opts.volumes_from(volumes_from.iter().filter_map(|from| from.as_str()));
+    }
+    if let Ok(devices) = serde_json::from_value::<Vec<Labels>>(host_config["Devices"].clone()) {
+        if !devices.is_empty() {
+            opts = opts.devices(devices);
+        }
+    }
+    if let Some(log_driver) = host_config["LogConfig"]["Type"]
+        .as_str()
+        .filter(|driver| !driver.is_empty())
+    {
+        opts = opts.log_driver(log_driver);
+    }
+    if let Some(memory) = host_config["Memory"].as_u64().filter(|memory| *memory > 0) {
+        opts = opts.memory(memory);
+    }
+    if let Some(memory_swap) = host_config["MemorySwap"].as_i64().filter(|swap| *swap != 0) {
+        opts = opts.memory_swap(memory_swap);
+    }
+    if let Some(nano_cpus) = host_config["NanoCpus"].as_u64().filter(|cpus| *cpus > 0) {
+        opts = opts.nano_cpus(nano_cpus);
+    }
+    if let Some(cpu_shares) = host_config["CpuShares"]
+        .as_u64()
+        .and_then(|shares| u32::try_from(shares).ok())
+        .filter(|shares| *shares > 0)
+    {
+        opts = opts.cpu_shares(cpu_shares);
+    }
+    if let Some(userns_mode) = host_config["UsernsMode"]
+        .as_str()
+        .filter(|mode| !mode.is_empty())
+    {
+        opts = opts.userns_mode(userns_mode);
+    }
+    if host_config["Privileged"].as_bool() == Some(true) {
+        opts = opts.privileged(true);
+    }
+
+    opts.build()
+}
+
+/// Creates the new container, previous one is brought back when that fails
+async fn create_new_container(
+    docker: &Docker,
+    previous: &Container,
+    opts: &ContainerCreateOpts,
+    name: &str,
+    was_running: bool,
+) -> Result<Container> {
+    match docker.containers().create(opts).await {
+        Ok(new_container) => Ok(new_container),
+        Err(e) => {
+            error!(
+                "Failed creating container {}, restoring previous one: {}",
+                name, e
+            );
+            restore_previous_container(previous, None, name, was_running).await?;
+
+            Err(e.into())
+        }
+    }
+}
+
+/// Connects recreated container to networks of the previous one and starts it
+async fn start_new_container(
+    docker: &Docker,
+    new_container: &Container,
+    networks: HashMap<String, EndpointSettings>,
+    network_mode: &str,
+) -> Result<()> {
+    // Only network mode network is connected on create, compose containers can be in several
+    for (network, endpoint) in networks {
+        if network == network_mode {
+            continue;
+        }
+
+        docker
+            .networks()
+            .get(&network)
+            .connect(
+                &ContainerConnectionOpts::builder(new_container.id())
+                    .aliases(endpoint.aliases.unwrap_or_default())
+                    .build(),
+            )
+            .await?;
+    }
+
+    new_container.start().await?;
 
     Ok(())
 }
+
+/// Removes the failed new container and brings back the previous one under its original name
+async fn restore_previous_container(
+    previous: &Container,
+    new_container: Option<&Container>,
+    name: &str,
+    was_running: bool,
+) -> Result<()> {
+    if let Some(new_container) = new_container {
+        new_container
+            .remove(&ContainerRemoveOpts::builder().force(true).build())
+            .await?;
+    }
+    previous.rename(name).await?;
+    if was_running {
+        previous.start().await?;
+    }
+
+    Ok(())
+}
+
+/// Lists container config properties that differ from the image and cannot be carried over
+fn unsupported_config(
+    config: &ContainerConfig,
+    image_config: &ContainerConfig,
+    container_id: &str,
+) -> Vec<String> {
+    let mut unsupported = vec![];
+
+    // Docker uses short container id as hostname unless one is given
+    if config
+        .hostname
+        .as_deref()
+        .is_some_and(|hostname| !hostname.is_empty() && !container_id.starts_with(hostname))
+    {
+        unsupported.push("Hostname");
+    }
+    if config
+        .domainname
+        .as_deref()
+        .is_some_and(|name| !name.is_empty())
+    {
+        unsupported.push("Domainname");
+    }
+    if config
+        .mac_address
+        .as_deref()
+        .is_some_and(|mac| !mac.is_empty())
+    {
+        unsupported.push("MacAddress");
+    }
+    if config.network_disabled == Some(true) {
+        unsupported.push("NetworkDisabled");
+    }
+    if config.healthcheck.is_some() && config.healthcheck != image_config.healthcheck {
+        unsupported.push("Healthcheck");
+    }
+    if config.shell.is_some() && config.shell != image_config.shell {
+        unsupported.push("Shell");
+    }
+    if config.stop_timeout.is_some() && config.stop_timeout != image_config.stop_timeout {
+        unsupported.push("StopTimeout");
+    }
+
+    unsupported.into_iter().map(str::to_string).collect()
+}
+
+/// Lists host config properties that are set but cannot be carried over
+fn unsupported_host_config(host_config: &Value) -> Vec<String> {
+    let Some(host_config) = host_config.as_object() else {
+        return vec![];
+    };
+
+    let mut unsupported: Vec<_> = host_config
+        .iter()
+        .filter(|(key, value)| {
+            !RECREATED_HOST_CONFIG.contains(&key.as_str())
+                && !is_default_host_config_value(key, value)
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    if host_config
+        .get("LogConfig")
+        .and_then(|log_config| log_config["Config"].as_object())
+        .is_some_and(|options| !options.is_empty())
+    {
+        unsupported.push("LogConfig.Config".to_string());
+    }
+    // Only host port of a binding can be set, random ports and host addresses are lost
+    let port_bindings = host_config
+        .get("PortBindings")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flat_map(|bindings| bindings.values())
+        .filter_map(Value::as_array);
+    for bindings in port_bindings {
+        if bindings.len() > 1
+            || bindings.iter().any(|binding| {
+                !matches!(
+                    binding["HostIp"].as_str(),
+                    None | Some("" | "0.0.0.0" | "::")
+                ) || binding["HostPort"]
+                    .as_str()
+                    .and_then(|port| port.parse::<u32>().ok())
+                    .is_none()
+            })
+        {
+            unsupported.push("PortBindings".to_string());
+            break;
+        }
+    }
+
+    unsupported
+}
+
+/// Whether host config property has the value Docker uses when the property is not set
+fn is_default_host_config_value(key: &str, value: &Value) -> bool {
+    match key {
+        // Filled in by Docker daemon on create
+        "ConsoleSize" | "MaskedPaths" | "ReadonlyPaths" | "CgroupnsMode" => true,
+        "IpcMode" => matches!(value.as_str(), Some("" | "private" | "shareable")),
+        "ShmSize" => matches!(value.as_u64(), Some(0 | DEFAULT_SHM_SIZE)),
+        "Runtime" => matches!(value.as_str(), Some("" | "runc")),
+        "Isolation" => matches!(value.as_str(), Some("" | "default")),
+        _ => match value {
+            Value::Null => true,
+            Value::Bool(value) => !value,
+            Value::Number(number) => number.as_f64() == Some(0.0),
+            Value::String(value) => value.is_empty(),
+            Value::Array(values) => values.is_empty(),
+            Value::Object(values) => values.is_empty(),
+        },
+    }
+}
+
+/// Port keys of Docker API include protocol, it defaults to TCP when missing
+fn port_with_protocol(port: &str) -> String {
+    if port.contains('/') {
+        port.to_string()
+    } else {
+        format!("{}/tcp", port)
+    }
+}
+
+/// Converts mount to `source:destination[:options]` bind, named volumes are referenced by their
+/// name. Read-only flag, SELinux labels and non-default propagation are kept.
+fn mount_bind(mount: &MountPoint) -> Option<String> {
+    let source = match mount.type_.as_deref() {
+        Some("volume") => mount.name.as_ref()?,
+        Some("bind") => mount.source.as_ref()?,
+        _ => return None,
+    };
+    let destination = mount.destination.as_ref()?;
+
+    let mut options: Vec<&str> = mount
+        .mode
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|option| !option.is_empty() && *option != "ro" && *option != "rw")
+        .collect();
+    if mount.rw == Some(false) {
+        options.insert(0, "ro");
+    }
+    if let Some(propagation) = mount.propagation.as_deref().filter(|propagation| {
+        mount.type_.as_deref() == Some("bind")
+            && !propagation.is_empty()
+            && *propagation != "rprivate"
+            && !options.contains(propagation)
+    }) {
+        options.push(propagation);
+    }
+
+    Some(if options.is_empty() {
+        format!("{}:{}", source, destination)
+    } else {
+        format!("{}:{}:{}", source, destination, options.join(","))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn time(hour: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn exited_status() -> ContainerStatus {
+        ContainerStatus {
+            id: "0123456789ab".to_string(),
+            state: "exited".to_string(),
+            health: None,
+            exit_code: Some(137),
+            started_at: Some(time(1)),
+            finished_at: Some(time(2)),
+            oom_killed_at: None,
+        }
+    }
+
+    #[test]
+    fn start_and_die_events_update_state() {
+        let mut status = exited_status();
+
+        assert!(status.apply_event("start", &HashMap::new(), time(3)));
+        assert_eq!(status.state, "running");
+        assert_eq!(status.started_at, Some(time(3)));
+        assert_eq!(status.exit_code, None);
+        assert_eq!(status.finished_at, Some(time(2)));
+
+        assert!(status.apply_event("health_status: healthy", &HashMap::new(), time(4)));
+        assert_eq!(status.health.as_deref(), Some("healthy"));
+
+        let attributes = HashMap::from([("exitCode".to_string(), "1".to_string())]);
+        assert!(status.apply_event("die", &attributes, time(5)));
+        assert_eq!(status.state, "exited");
+        assert_eq!(status.finished_at, Some(time(5)));
+        assert_eq!(status.exit_code, Some(1));
+        assert_eq!(status.health, None);
+        assert_eq!(status.started_at, Some(time(3)));
+
+        assert!(status.apply_event("restart", &HashMap::new(), time(6)));
+        assert_eq!(status.state, "running");
+        assert_eq!(status.started_at, Some(time(6)));
+    }
+
+    #[test]
+    fn oom_event_is_recorded_without_changing_state() {
+        let mut status = exited_status();
+        status.apply_event("start", &HashMap::new(), time(3));
+
+        assert!(status.apply_event("oom", &HashMap::new(), time(4)));
+        assert_eq!(status.oom_killed_at, Some(time(4)));
+        assert_eq!(status.state, "running");
+    }
+
+    #[test]
+    fn unrelated_events_are_ignored() {
+        let mut status = exited_status();
+
+        assert!(!status.apply_event("attach", &HashMap::new(), time(3)));
+        assert!(!status.apply_event("exec_start: odin status", &HashMap::new(), time(3)));
+        assert_eq!(status, exited_status());
+    }
+
+    #[test]
+    fn split_image_reference_handles_tags_ports_and_digests() {
+        assert_eq!(
+            split_image_reference("lloesche/valheim-server"),
+            ("lloesche/valheim-server", "latest")
+        );
+        assert_eq!(
+            split_image_reference("mbround18/valheim:3"),
+            ("mbround18/valheim", "3")
+        );
+        assert_eq!(
+            split_image_reference("localhost:5000/valheim"),
+            ("localhost:5000/valheim", "latest")
+        );
+        assert_eq!(
+            split_image_reference("localhost:5000/valheim:dev"),
+            ("localhost:5000/valheim", "dev")
+        );
+        assert_eq!(
+            split_image_reference("mbround18/valheim@sha256:abc"),
+            ("mbround18/valheim", "sha256:abc")
+        );
+        assert_eq!(
+            split_image_reference("localhost:5000/valheim:3@sha256:abc"),
+            ("localhost:5000/valheim", "sha256:abc")
+        );
+    }
+
+    #[test]
+    fn default_host_config_is_supported() {
+        let host_config = json!({
+            "Binds": ["/srv/valheim:/config"],
+            "NetworkMode": "bridge",
+            "PortBindings": {"2456/udp": [{"HostIp": "", "HostPort": "2456"}]},
+            "RestartPolicy": {"Name": "unless-stopped", "MaximumRetryCount": 0},
+            "LogConfig": {"Type": "json-file", "Config": {}},
+            "ConsoleSize": [0, 0],
+            "IpcMode": "private",
+            "ShmSize": DEFAULT_SHM_SIZE,
+            "Runtime": "runc",
+            "Dns": [],
+            "Ulimits": null,
+            "OomKillDisable": false,
+            "PidsLimit": 0,
+            "CpuQuota": 0,
+        });
+
+        assert!(unsupported_host_config(&host_config).is_empty());
+    }
+
+    #[test]
+    fn customized_host_config_is_reported() {
+        let host_config = json!({
+            "Dns": ["1.1.1.1"],
+            "Ulimits": [{"Name": "nofile", "Soft": 1024, "Hard": 2048}],
+            "ShmSize": 2 * DEFAULT_SHM_SIZE,
+            "LogConfig": {"Type": "json-file", "Config": {"max-size": "10m"}},
+            "PortBindings": {"2456/udp": [{"HostIp": "127.0.0.1", "HostPort": "2456"}]},
+            "Memory": 4_000_000_000u64,
+        });
+
+        let mut unsupported = unsupported_host_config(&host_config);
+        unsupported.sort();
+
+        assert_eq!(
+            unsupported,
+            [
+                "Dns",
+                "LogConfig.Config",
+                "PortBindings",
+                "ShmSize",
+                "Ulimits"
+            ]
+        );
+    }
+
+    #[test]
+    fn mount_bind_keeps_options() {
+        let mount = |type_: &str, mode: &str, rw: bool, propagation: &str| MountPoint {
+            type_: Some(type_.to_string()),
+            name: Some("valheim-data".to_string()),
+            source: Some("/srv/valheim".to_string()),
+            destination: Some("/config".to_string()),
+            mode: Some(mode.to_string()),
+            rw: Some(rw),
+            propagation: Some(propagation.to_string()),
+            driver: None,
+        };
+
+        assert_eq!(
+            mount_bind(&mount("bind", "", true, "rprivate")).as_deref(),
+            Some("/srv/valheim:/config")
+        );
+        assert_eq!(
+            mount_bind(&mount("bind", "ro,Z", false, "rslave")).as_deref(),
+            Some("/srv/valheim:/config:ro,Z,rslave")
+        );
+        assert_eq!(
+            mount_bind(&mount("volume", "z", true, "")).as_deref(),
+            Some("valheim-data:/config:z")
+        );
+        assert_eq!(mount_bind(&mount("tmpfs", "", true, "")), None);
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+/// Kind of value an environment variable holds, determines its validation and form input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvVarKind {
+    Text,
+    Secret,
+    Flag,
+    Number,
+    Cron,
+}
+
+/// valheim-docker environment variable editable from the dashboard
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvVar {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: EnvVarKind,
+    pub required: bool,
+}
+
+const fn env_var(
+    name: &'static str,
+    description: &'static str,
+    kind: EnvVarKind,
+    required: bool,
+) -> EnvVar {
+    EnvVar {
+        name,
+        description,
+        kind,
+        required,
+    }
+}
+
+/// Variables of mbround18/valheim-docker that are safe to change, others (e.g. `PORT`) need
+/// matching changes of the container itself
+pub const EDITABLE_ENV_VARS: [EnvVar; 15] = [
+    env_var(
+        "NAME",
+        "Server name shown in the server browser",
+        EnvVarKind::Text,
+        true,
+    ),
+    env_var("WORLD", "World name", EnvVarKind::Text, true),
+    env_var("PASSWORD", "Server password", EnvVarKind::Secret, false),
+    env_var(
+        "PUBLIC",
+        "List server in the server browser",
+        EnvVarKind::Flag,
+        false,
+    ),
+    env_var(
+        "CROSSPLAY",
+        "Allow players from all platforms",
+        EnvVarKind::Flag,
+        false,
+    ),
+    env_var("PRESET", "World modifiers preset", EnvVarKind::Text, false),
+    env_var(
+        "AUTO_UPDATE",
+        "Update server automatically",
+        EnvVarKind::Flag,
+        false,
+    ),
+    env_var(
+        "AUTO_UPDATE_SCHEDULE",
+        "Cron schedule of update checks",
+        EnvVarKind::Cron,
+        false,
+    ),
+    env_var(
+        "AUTO_BACKUP",
+        "Back up saves automatically",
+        EnvVarKind::Flag,
+        false,
+    ),
+    env_var(
+        "AUTO_BACKUP_SCHEDULE",
+        "Cron schedule of backups",
+        EnvVarKind::Cron,
+        false,
+    ),
+    env_var(
+        "AUTO_BACKUP_REMOVE_OLD",
+        "Remove old backups",
+        EnvVarKind::Flag,
+        false,
+    ),
+    env_var(
+        "AUTO_BACKUP_DAYS_TO_LIVE",
+        "Days to keep backups for",
+        EnvVarKind::Number,
+        false,
+    ),
+    env_var(
+        "AUTO_BACKUP_ON_UPDATE",
+        "Back up before updating",
+        EnvVarKind::Flag,
+        false,
+    ),
+    env_var(
+        "AUTO_BACKUP_ON_SHUTDOWN",
+        "Back up on shutdown",
+        EnvVarKind::Flag,
+        false,
+    ),
+    env_var(
+        "AUTO_BACKUP_PAUSE_WITH_NO_PLAYERS",
+        "Skip backups while no players are online",
+        EnvVarKind::Flag,
+        false,
+    ),
+];
+
+pub fn editable_env_var(name: &str) -> Option<&'static EnvVar> {
+    EDITABLE_ENV_VARS.iter().find(|var| var.name == name)
+}
+
+/// Parses `KEY=value` entries of container config, entries without `=` are variables with empty value
+pub fn parse_env(env: &[String]) -> BTreeMap<String, String> {
+    env.iter()
+        .map(|var| match var.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (var.clone(), String::new()),
+        })
+        .collect()
+}
+
+impl EnvVar {
+    /// Normalizes equivalent flag values so that they are not reported as changes
+    pub fn normalize<'a>(&self, value: &'a str) -> &'a str {
+        match (self.kind, value) {
+            (EnvVarKind::Flag, "true") => "1",
+            (EnvVarKind::Flag, "false") => "0",
+            _ => value,
+        }
+    }
+
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        if value.is_empty() {
+            return if self.required {
+                Err(format!("{} is required", self.name))
+            } else {
+                Ok(())
+            };
+        }
+        if value.contains(['\n', '\r', '\0']) {
+            return Err(format!("{} must be a single line", self.name));
+        }
+
+        match self.kind {
+            EnvVarKind::Text => Ok(()),
+            EnvVarKind::Secret if value.len() < 5 => {
+                Err(format!("{} must be at least 5 characters long", self.name))
+            }
+            EnvVarKind::Secret => Ok(()),
+            EnvVarKind::Flag => match value {
+                "0" | "1" | "true" | "false" => Ok(()),
+                _ => Err(format!("{} must be 0, 1, true or false", self.name)),
+            },
+            EnvVarKind::Number => value
+                .parse::<u32>()
+                .map(|_| ())
+                .map_err(|_| format!("{} must be a non-negative number", self.name)),
+            EnvVarKind::Cron => {
+                if value.split_whitespace().count() == 5 && !value.contains(['"', '\'']) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "{} must be a cron expression with 5 fields",
+                        self.name
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Collects submitted values that differ from `current_env`, reporting validation errors and
+/// submitted variables that are not editable
+pub fn collect_changes(
+    form: &HashMap<String, String>,
+    current_env: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>, Vec<String>> {
+    let mut errors: Vec<_> = form
+        .keys()
+        .filter(|key| editable_env_var(key).is_none())
+        .map(|key| format!("{} cannot be changed", key))
+        .collect();
+    errors.sort();
+
+    let mut env_changes = BTreeMap::new();
+    for var in &EDITABLE_ENV_VARS {
+        let Some(value) = form.get(var.name) else {
+            continue;
+        };
+        // Secrets are never sent to the page, empty one means unchanged
+        let value = match var.kind {
+            EnvVarKind::Secret if value.is_empty() => continue,
+            EnvVarKind::Secret => value.as_str(),
+            _ => value.trim(),
+        };
+
+        // Only changed values are validated, so current container values never block applying
+        if var.normalize(value)
+            == var.normalize(current_env.get(var.name).map_or("", String::as_str))
+        {
+            continue;
+        }
+        match var.validate(value) {
+            Ok(()) => {
+                env_changes.insert(var.name.to_string(), value.to_string());
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(env_changes)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn current_env() -> BTreeMap<String, String> {
+        parse_env(&[
+            "NAME=Valheim".to_string(),
+            "WORLD=Dedicated".to_string(),
+            "PASSWORD=secret".to_string(),
+            "PUBLIC=1".to_string(),
+            "PORT=2456".to_string(),
+        ])
+    }
+
+    #[test]
+    fn only_changed_values_are_collected() {
+        let form = vars(&[
+            ("NAME", " Valheim "),
+            ("WORLD", "Midgard"),
+            ("PASSWORD", ""),
+            ("PUBLIC", "true"),
+            ("AUTO_BACKUP_DAYS_TO_LIVE", "3"),
+        ]);
+
+        let changes = collect_changes(&form, &current_env()).unwrap();
+
+        assert_eq!(
+            changes,
+            BTreeMap::from([
+                ("AUTO_BACKUP_DAYS_TO_LIVE".to_string(), "3".to_string()),
+                ("WORLD".to_string(), "Midgard".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn variables_not_on_whitelist_are_rejected() {
+        let form = vars(&[("WORLD", "Midgard"), ("PORT", "2460"), ("SERVER_ARGS", "")]);
+
+        let errors = collect_changes(&form, &current_env()).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "PORT cannot be changed".to_string(),
+                "SERVER_ARGS cannot be changed".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_changed_values_are_reported() {
+        let form = vars(&[
+            ("NAME", ""),
+            ("PASSWORD", "abc"),
+            ("PUBLIC", "yes"),
+            ("AUTO_BACKUP_SCHEDULE", "*/5 * *"),
+            ("WORLD", "Mid\ngard"),
+        ]);
+
+        let errors = collect_changes(&form, &current_env()).unwrap_err();
+
+        assert_eq!(errors.len(), 5);
+    }
+
+    #[test]
+    fn environment_entries_are_parsed() {
+        let env = parse_env(&["NAME=a=b".to_string(), "EMPTY".to_string()]);
+
+        assert_eq!(env["NAME"], "a=b");
+        assert_eq!(env["EMPTY"], "");
+    }
+}
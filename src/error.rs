@@ -10,9 +10,27 @@ pub enum ValmanError {
     #[error("Docker error - {0}")]
     Docker(String),
 
+    #[error("Docker container not found by {0}")]
+    ContainerNotFound(String),
+
+    #[error("Last restart was less than {0} seconds ago")]
+    RestartTooSoon(u32),
+
     #[error("Valve A2S error - {0}")]
     ValveA2S(#[from] a2s::errors::Error),
 
     #[error("Backup error - {0}")]
     Backup(#[from] std::io::Error),
+
+    #[error("World error - {0}")]
+    World(String),
+
+    #[error("State store error - {0}")]
+    Store(String),
+
+    #[error("Template error - {0}")]
+    Template(#[from] minijinja::Error),
+
+    #[error("File watcher error - {0}")]
+    Watcher(#[from] notify::Error),
 }
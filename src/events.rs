@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+use docker_api::{models::EventMessage, Docker};
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{
+    docker::{self, ContainerStatus},
+    SharedState,
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const STATUS_UPDATES_CAPACITY: usize = 16;
+
+/// Container status change of a managed server, pushed to connected browsers
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusUpdate {
+    pub server_id: String,
+    #[serde(flatten)]
+    pub status: ContainerStatus,
+    pub uptime: String,
+}
+
+impl StatusUpdate {
+    fn new(server_id: String, status: ContainerStatus) -> Self {
+        Self {
+            server_id,
+            uptime: status.uptime(),
+            status,
+        }
+    }
+}
+
+pub fn status_updates_channel() -> broadcast::Sender<StatusUpdate> {
+    broadcast::channel(STATUS_UPDATES_CAPACITY).0
+}
+
+/// Keeps cached container status of every server up to date from the Docker events stream.
+/// Statuses are refreshed by inspecting the containers whenever the stream (re)connects.
+pub fn spawn_docker_events_watcher(state: SharedState) {
+    tokio::spawn(async move {
+        loop {
+            let docker = state.read().await.docker.clone();
+            let mut events = docker::container_events(&docker);
+
+            refresh_statuses(&state, &docker).await;
+            info!("Subscribing to Docker container events");
+
+            while let Some(event) = events.next().await {
+                match event {
+                    Ok(event) => handle_event(&state, &docker, event).await,
+                    Err(e) => {
+                        error!("Docker events stream failed: {}", e);
+
+                        break;
+                    }
+                }
+            }
+
+            warn!(
+                "Docker events stream closed, reconnecting in {}s",
+                RECONNECT_DELAY.as_secs()
+            );
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn refresh_statuses(state: &SharedState, docker: &Docker) {
+    let servers = state.read().await.config.servers.clone();
+
+    for server in servers {
+        match docker::retrieve_container_status(docker, &server.container).await {
+            Ok(status) => update_status(state, &server.id, status).await,
+            Err(e) => {
+                warn!(
+                    "Failed refreshing container status of server {}: {}",
+                    server.id, e
+                );
+
+                state.write().await.container_statuses.remove(&server.id);
+            }
+        }
+    }
+}
+
+async fn handle_event(state: &SharedState, docker: &Docker, event: EventMessage) {
+    let (Some(action), Some(actor)) = (event.action, event.actor) else {
+        return;
+    };
+    let container_id = actor.id.unwrap_or_default();
+    let attributes = actor.attributes.unwrap_or_default();
+    let time = event
+        .time
+        .and_then(|time| Local.timestamp_opt(time, 0).single())
+        .map(|time| time.naive_local())
+        .unwrap_or_else(|| Local::now().naive_local());
+
+    let (servers, cached_statuses) = {
+        let state = state.read().await;
+
+        (
+            state.config.servers.clone(),
+            state.container_statuses.clone(),
+        )
+    };
+
+    for server in servers {
+        let cached_status = cached_statuses
+            .get(&server.id)
+            .filter(|status| status.id == container_id);
+        if cached_status.is_none()
+            && !docker::selector_matches(&server.container, &container_id, &attributes)
+        {
+            continue;
+        }
+
+        debug!(
+            "Container event {} of server {} ({})",
+            action, server.id, container_id
+        );
+
+        // Container unknown so far, e.g. recreated by compose, has to be inspected first
+        let mut status = match cached_status {
+            Some(status) => status.clone(),
+            None => match docker::retrieve_container_status(docker, &server.container).await {
+                Ok(status) => status,
+                Err(e) => {
+                    error!("Failed inspecting container of server {}: {}", server.id, e);
+
+                    continue;
+                }
+            },
+        };
+
+        if status.apply_event(&action, &attributes, time) {
+            if action == "oom" {
+                warn!("Container of server {} ran out of memory", server.id);
+            }
+
+            update_status(state, &server.id, status).await;
+        }
+    }
+}
+
+async fn update_status(state: &SharedState, server_id: &str, status: ContainerStatus) {
+    let mut state = state.write().await;
+
+    if state.container_statuses.get(server_id) == Some(&status) {
+        return;
+    }
+
+    state
+        .container_statuses
+        .insert(server_id.to_string(), status.clone());
+    // Nobody listening is fine, updates are only pushed to open pages
+    let _ = state
+        .status_updates
+        .send(StatusUpdate::new(server_id.to_string(), status));
+}
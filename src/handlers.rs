@@ -1,29 +1,148 @@
 use axum::{
     body::{self, Empty, Full, StreamBody},
-    extract::{Path, State},
-    http::{self, header, HeaderMap, HeaderValue, Request, Response, StatusCode},
+    extract::{Form, Multipart, Path, Query, State},
+    http::{self, header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode},
     middleware::Next,
-    response::{Html, IntoResponse, Redirect},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Redirect, Response as AxumResponse,
+    },
+    Extension,
+};
+use chrono::{DateTime, Local, NaiveDate};
+use docker_api::Docker;
+use futures_util::{stream, Stream};
+use log::{error, info, warn};
+use minijinja::context;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    sync::{broadcast::error::RecvError, mpsc},
 };
-use chrono::{DateTime, Local};
-use humansize::DECIMAL;
-use log::{debug, error};
-use std::{collections::HashMap, fs, time::SystemTime};
-use tokio::fs::File;
 use tokio_util::io::ReaderStream;
 
-use crate::{backup, docker, valve, version_with_commit, SharedState, SimpleDirEntry, STATIC_DIR};
+use crate::{
+    backup::{self, BackupSort, SimpleDirEntry},
+    config::{Config, ServerConfig, UserRole},
+    docker,
+    environment::{self, EnvVarKind, EDITABLE_ENV_VARS},
+    error::ValmanError,
+    odin::OdinCommand,
+    settings::{self, ServerSettings, WorldPreset},
+    store::{PersistentState, RestartEntry, RestartTrigger, ServerState, Store},
+    templates::Templates,
+    valve, version_with_commit, worlds, SharedState, STATIC_DIR,
+};
+
+const RESTART_RECOVERY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const RESTART_RECOVERY_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+const RESTART_HISTORY_DISPLAY_LEN: usize = 10;
+const BACKUPS_PAGE_SIZE: usize = 20;
+/// Format of dates sent by `<input type="date">`
+const DATE_INPUT_FORMAT: &str = "%Y-%m-%d";
+const BACKUP_LABELS_MAX_COUNT: usize = 10;
+const BACKUP_LABEL_MAX_LEN: usize = 32;
+const BACKUP_NOTE_MAX_LEN: usize = 1000;
+/// Characters escaped in names placed in URL path, unreserved ones are kept
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+/// Header reverse proxies pass the host requested by the browser in
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+/// Log target of actions that permanently remove data, can be routed to a separate appender
+const AUDIT_LOG_TARGET: &str = "valman::audit";
+/// How long backup deletion can be confirmed after the confirmation page is shown
+const DELETE_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+pub(crate) const WORLD_UPLOAD_MAX_SIZE: usize = 512 * 1024 * 1024;
+/// Size of chunks world download is streamed in and how many of them can wait to be sent
+const WORLD_DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+const WORLD_DOWNLOAD_CHANNEL_CAPACITY: usize = 4;
+pub(crate) const BACKUP_UPLOAD_MAX_SIZE: usize = 2 * 1024 * 1024 * 1024;
+const BACKUP_UPLOAD_EXTENSIONS: [&str; 5] = [".tar.gz", ".tgz", ".tar.zst", ".tar", ".zip"];
+
+#[derive(Debug, Clone)]
+pub(crate) struct AuthenticatedUser(pub String, pub UserRole);
+
+/// Backup deletion waiting for confirmation. Deletion is carried out only when its confirmation
+/// token is submitted back, so a forged request cannot skip the confirmation page.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingDeletion {
+    username: String,
+    server_id: String,
+    backups: Vec<String>,
+    expires_at: Instant,
+}
+
+/// Blocking writer passing written data to a streamed response body
+struct ChannelWriter(mpsc::Sender<io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "download was cancelled"))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OdinForm {
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BackupsQuery {
+    page: Option<usize>,
+    #[serde(default)]
+    sort: BackupSort,
+    #[serde(default)]
+    order: SortOrder,
+    /// First day of backups shown, as `YYYY-MM-DD`
+    from: Option<String>,
+    /// Last day of backups shown, as `YYYY-MM-DD`
+    to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BackupMetadataForm {
+    #[serde(default, deserialize_with = "settings::deserialize_checkbox")]
+    pinned: bool,
+    /// Comma separated labels
+    #[serde(default)]
+    labels: String,
+    #[serde(default)]
+    note: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
 
 pub(crate) async fn auth<B>(
     state: State<SharedState>,
-    req: Request<B>,
+    mut req: Request<B>,
     next: Next<B>,
 ) -> Result<axum::response::Response, (HeaderMap, StatusCode)> {
-    let (username, password) = {
-        let state = state.read().await;
-
-        (state.config.username.clone(), state.config.password.clone())
-    };
+    let config = state.read().await.config.clone();
 
     let auth_header = req
         .headers()
@@ -42,48 +161,135 @@ pub(crate) async fn auth<B>(
     let credentials = http_auth_basic::Credentials::from_header(auth_header.to_string())
         .map_err(|_| (www_auth_headers.clone(), StatusCode::UNAUTHORIZED))?;
 
-    if credentials.user_id.eq_ignore_ascii_case(&username)
-        && credentials.password.eq_ignore_ascii_case(&password)
-    {
+    if let Some(role) = config.authenticate(&credentials.user_id, &credentials.password) {
+        req.extensions_mut()
+            .insert(AuthenticatedUser(credentials.user_id, role));
+
         Ok(next.run(req).await)
     } else {
         Err((www_auth_headers, StatusCode::UNAUTHORIZED))
     }
 }
 
-pub(crate) async fn root_handler(
+/// Rejects form submissions made from other sites. Browsers send cached Basic auth credentials
+/// with cross-site requests as well, so any page could otherwise act on behalf of a logged in user.
+/// Requests without `Origin` and `Referer` headers (e.g. from scripts) are let through.
+pub(crate) async fn same_origin<B>(
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    if req.method() == Method::GET || req.method() == Method::HEAD {
+        return Ok(next.run(req).await);
+    }
+
+    let headers = req.headers();
+    let header_str = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    let host = header_str(X_FORWARDED_HOST).or_else(|| header_str(header::HOST.as_str()));
+    let source =
+        header_str(header::ORIGIN.as_str()).or_else(|| header_str(header::REFERER.as_str()));
+    if let Some(source) = source {
+        let source_host = source
+            .split_once("://")
+            .map(|(_, rest)| rest.split('/').next().unwrap_or_default());
+        let same_site = source_host
+            .zip(host)
+            .is_some_and(|(source_host, host)| source_host.eq_ignore_ascii_case(host));
+        if !same_site {
+            warn!(
+                "Rejected {} {} from {}, which is not the site itself",
+                req.method(),
+                req.uri(),
+                source
+            );
+
+            return Err((
+                StatusCode::FORBIDDEN,
+                "Cross-site request rejected".to_string(),
+            ));
+        }
+    }
+
+    Ok(next.run(req).await)
+}
+
+pub(crate) async fn root_handler(State(state): State<SharedState>) -> impl IntoResponse {
+    let server_id = state.read().await.config.default_server().id.clone();
+
+    Redirect::to(&format!("/servers/{}", server_id))
+}
+
+pub(crate) async fn server_handler(
     State(state): State<SharedState>,
+    Path(server_id): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     let render_start = SystemTime::now();
 
-    let (docker, a2s_client, config, last_restart_time, template) = {
+    let (
+        docker,
+        a2s_client,
+        config,
+        server,
+        config_reload_error,
+        server_state,
+        container_status,
+        image_status,
+        odin_output,
+        templates,
+    ) = {
         let state = state.read().await;
+        let server = server_config(&state.config, &server_id)?;
 
         (
             state.docker.clone(),
             state.a2s_client.clone(),
             state.config.clone(),
-            state.last_restart_time,
-            state.template.clone(),
+            server,
+            state.config_reload_error.clone(),
+            read_server_state(&state.store, &server_id).await?,
+            state.container_statuses.get(&server_id).cloned(),
+            state.image_statuses.get(&server_id).cloned(),
+            state.odin_outputs.get(&server_id).cloned(),
+            state.templates.clone(),
         )
     };
+    let restart_history: Vec<_> = server_state
+        .restart_history
+        .iter()
+        .rev()
+        .take(RESTART_HISTORY_DISPLAY_LEN)
+        .collect();
 
-    let container_info = match docker::retrieve_container_info(
-        &docker,
-        &config.container_name,
-        config.valheim_server_last_log_lines_count as usize,
-    )
-    .await
-    {
-        Ok(cont_info) => Some(cont_info),
-        Err(e) => {
-            error!("Failed fetching Docker container info: {}", e);
+    // Status is kept up to date by Docker events, container is only inspected until the first refresh
+    let container_status = match container_status {
+        Some(container_status) => Some(container_status),
+        None => match docker::retrieve_container_status(&docker, &server.container).await {
+            Ok(container_status) => Some(container_status),
+            Err(e) => {
+                error!("Failed fetching Docker container info: {}", e);
 
-            None
+                None
+            }
+        },
+    };
+    let container_info = match container_status {
+        Some(container_status) => {
+            let logs = docker::retrieve_container_logs(
+                &docker,
+                &container_status.id,
+                config.last_log_lines_count as usize,
+            )
+            .await;
+
+            Some(docker::ContainerInfo::new(container_status, logs))
         }
+        None => None,
     };
     let valve_info =
-        match valve::retrieve_valve_info(&a2s_client, &config.valheim_server_address).await {
+        match valve::retrieve_valve_info(&a2s_client, &server.valheim_server_address).await {
             Ok(valve_info) => Some(valve_info),
             Err(e) => {
                 error!("Failed fetching Valve server info: {}", e);
@@ -92,272 +298,1967 @@ pub(crate) async fn root_handler(
             }
         };
 
-    let mut backup_files: Vec<_> = fs::read_dir(&config.valheim_backups_path)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .map(|r| r.unwrap())
-        .collect();
-    backup_files.sort_unstable_by_key(|dir| {
-        dir.metadata()
-            .expect("file metadata")
-            .created()
-            .expect("file metadata created")
-    });
-    let simple_backup_files: Vec<SimpleDirEntry> = backup_files
-        .into_iter()
-        .map(|bf| {
-            let metadata = bf.metadata().expect("file metadata");
-            let creation_time: DateTime<Local> = metadata
-                .created()
-                .expect("file metadata creation time")
-                .into();
-            let name = bf.file_name().to_string_lossy().to_string();
-
-            SimpleDirEntry {
-                name,
-                creation_time: creation_time.naive_local(),
-                hr_size: humansize::format_size(metadata.len(), DECIMAL),
-            }
-        })
-        .collect();
-    let files_iter = if simple_backup_files.len() > 5 {
+    let simple_backup_files = backup::list_backups(&server.valheim_backups_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let backups = if simple_backup_files.len() > 5 {
         &simple_backup_files[simple_backup_files.len() - 5..]
     } else {
         simple_backup_files.as_slice()
     };
-    let mut backup_files_templated = String::new();
-    for backup_file in files_iter {
-        backup_files_templated.push_str(&format!(
-            r#"<tr><td><a href="/backups/{}">{}</a></td><td>{}</td><td>{}</td><td style="text-align: end;"><a href="/backups/restore/{}" class="restore-btn" role="button" style="padding: 10px; width: 100%;">Restore</a></td></tr>"#,
-            backup_file.name,
-            backup_file.name,
-            backup_file.creation_time.format("%Y-%m-%d %H:%M:%S"),
-            backup_file.hr_size,
-            backup_file.name
-        ));
-    }
 
-    let restart_btn_html =
-        r#"<a id="restart-btn" href="/restart" role="button" style="height: 64px;">Restart</a>"#;
-    let restart_btn_wait = format!(
-        r#"<small style="line-height: 64px;">Last restart was less than {} seconds ago, please wait...</small>"#,
-        config.valheim_server_restart_delay_seconds
-    );
+    // TODO: Valheim currently does not report any meaningful player information :(
+    let page = templates
+        .render_main(context! {
+            version => version_with_build_time(),
+            servers => config.servers,
+            server,
+            config_reload_error,
+            container => container_info,
+            image => image_status,
+            odin_output,
+            valve => valve_info,
+            last_restart_time => server_state.last_restart_time,
+            restart_allowed => server_state.restart_allowed(server.restart_delay_seconds),
+            restart_delay_seconds => server.restart_delay_seconds,
+            restart_history,
+            backups,
+            backup_records => server_state.backups,
+            backup_upload_max_size => humansize::format_size(BACKUP_UPLOAD_MAX_SIZE, humansize::DECIMAL),
+            render_time => render_start.elapsed().unwrap().as_millis(),
+        })
+        .map_err(|e| {
+            error!("Failed rendering page: {}", e);
 
-    let mut replace_map: HashMap<String, String> = HashMap::new();
-    let build_timestamp: DateTime<Local> = DateTime::from(
-        DateTime::parse_from_rfc3339(env!("VERGEN_BUILD_TIMESTAMP"))
-            .expect("parse build timestamp"),
-    );
-    replace_map.insert(
-        "%version%".to_string(),
-        format!(
-            "{} (built {})",
-            version_with_commit(),
-            build_timestamp.format("%Y-%m-%d %H:%M:%S")
-        ),
-    );
-    replace_map.insert(
-        "%container_status%".to_string(),
-        container_info
-            .as_ref()
-            .map(|ci| ci.state.clone())
-            .unwrap_or_else(|| "n/a".to_string()),
-    );
-    replace_map.insert(
-        "%container_uptime%".to_string(),
-        container_info
-            .as_ref()
-            .map(|ci| ci.uptime.clone())
-            .unwrap_or_else(|| "n/a".to_string()),
-    );
-    replace_map.insert(
-        "%server_version%".to_string(),
-        valve_info
-            .as_ref()
-            .map(|vi| vi.version.clone())
-            .unwrap_or_else(|| "n/a".to_string()),
-    );
-    replace_map.insert(
-        "%player_count%".to_string(),
-        valve_info
-            .as_ref()
-            .map(|vi| vi.player_count.to_string())
-            .unwrap_or_else(|| "n/a".to_string()),
-    );
-    replace_map.insert(
-        "%last_restart_time%".to_string(),
-        last_restart_time
-            .map(|rt| rt.format("%Y-%m-%d %H:%M:%S").to_string())
-            .unwrap_or_else(|| "n/a".to_string()),
-    );
-    replace_map.insert(
-        "%server_logs%".to_string(),
-        container_info
-            .as_ref()
-            .map(|ci| ci.logs.clone())
-            .unwrap_or_else(|| "n/a".to_string()),
-    );
-    replace_map.insert("%backups%".to_string(), backup_files_templated);
-    let restart_btn = match last_restart_time {
-        Some(last_restart) => {
-            if last_restart
-                .signed_duration_since(Local::now().naive_local())
-                .num_seconds()
-                .abs()
-                > config.valheim_server_restart_delay_seconds.into()
-            {
-                restart_btn_html
-            } else {
-                &restart_btn_wait
-            }
-        }
-        None => restart_btn_html,
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    Ok(Html(page))
+}
+
+/// Pushes container status changes of the server to the page as server-sent events
+pub(crate) async fn server_events_handler(
+    State(state): State<SharedState>,
+    Path(server_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, (StatusCode, String)> {
+    let status_updates = {
+        let state = state.read().await;
+        server_config(&state.config, &server_id)?;
+
+        state.status_updates.subscribe()
     };
-    replace_map.insert("%restart_btn%".to_string(), restart_btn.to_string());
-    // TODO: Valheim currently does not report any meaningful player information :(
-    // let mut players_str = String::new();
-    // for player in valve_info.players {
-    //     players_str.push_str(&format!("<td>{}</td>", player.name));
-    // }
-    // replace_map.insert("%players%".to_string(), players_str);
-    if container_info
-        .as_ref()
-        .map(|ci| ci.state.clone())
-        .unwrap_or_else(|| "n/a".to_string())
-        .eq_ignore_ascii_case("running")
-    {
-        replace_map.insert("%container_status_img%".to_string(), "ok".to_string());
-    } else {
-        replace_map.insert("%container_status_img%".to_string(), "cross".to_string());
-    }
 
-    replace_map.insert(
-        "%render_time%".to_string(),
-        render_start.elapsed().unwrap().as_millis().to_string(),
-    );
+    let events = stream::unfold(status_updates, move |mut status_updates| {
+        let server_id = server_id.clone();
+
+        async move {
+            loop {
+                match status_updates.recv().await {
+                    Ok(update) if update.server_id == server_id => {
+                        return Some((Event::default().json_data(&update), status_updates));
+                    }
+                    // Skipped updates are fine, the next one carries the full status
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
 
-    Ok(Html(render_template(&template, &replace_map)))
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
 }
 
 pub(crate) async fn restart_handler(
     State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    {
+    let server = {
         let state = state.read().await;
-        docker::restart_container(&state.docker, &state.config.container_name)
+        let server = server_config(&state.config, &server_id)?;
+
+        docker::restart_container(&state.docker, &server.container)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    }
 
-    {
-        let mut state = state.write().await;
-        state.last_restart_time = Some(Local::now().naive_local());
-    }
+        server
+    };
+
+    record_restart(&state, &server, RestartTrigger::Manual, Some(user.0)).await;
 
-    Ok(Redirect::to("/"))
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
 }
 
-pub(crate) async fn backups_handler(
+pub(crate) async fn image_check_handler(
     State(state): State<SharedState>,
-    Path(name): Path<String>,
-) -> impl IntoResponse {
-    let mut backup_file_path = {
+    Path(server_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (docker, server) = {
         let state = state.read().await;
 
-        state.config.valheim_backups_path.clone()
+        (
+            state.docker.clone(),
+            server_config(&state.config, &server_id)?,
+        )
     };
-    backup_file_path.push(name);
 
-    let mime_type = mime_guess::from_path(&backup_file_path).first_or_text_plain();
+    let image_status = docker::check_image_update(&docker, &server.container)
+        .await
+        .map_err(|e| {
+            error!("Failed checking image update: {}", e);
 
-    let file = match File::open(&backup_file_path).await {
-        Ok(file) => file,
-        Err(err) => return Err((StatusCode::NOT_FOUND, format!("File not found: {}", err))),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed checking image update: {}", e),
+            )
+        })?;
+    state
+        .write()
+        .await
+        .image_statuses
+        .insert(server.id.clone(), image_status);
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
+}
+
+/// Takes a backup, pulls the newest image and recreates the container from it
+pub(crate) async fn update_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (docker, server) = {
+        let state = state.read().await;
+
+        (
+            state.docker.clone(),
+            server_config(&state.config, &server_id)?,
+        )
     };
-    let stream = ReaderStream::new(file);
-    let body = StreamBody::new(stream);
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header(
-            header::CONTENT_TYPE,
-            HeaderValue::from_str(mime_type.as_ref()).unwrap(),
+    let container = docker::ensure_recreatable(&server.container)
+        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+
+    let backup_path = create_backup(&state, &server).await.map_err(|e| {
+        error!("Failed creating backup before update: {}", e);
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed creating backup before update: {}", e),
         )
-        .body(body)
-        .unwrap())
+    })?;
+    info!(
+        "Created backup {} before updating server {}",
+        backup_path.display(),
+        server.id
+    );
+
+    docker::update_container(&docker, container)
+        .await
+        .map_err(|e| {
+            error!("Failed updating container: {}", e);
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed updating container: {}", e),
+            )
+        })?;
+    state.write().await.image_statuses.remove(&server.id);
+
+    record_restart(&state, &server, RestartTrigger::Update, Some(user.0)).await;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
 }
 
-pub(crate) async fn backups_restore_handler(
+/// Runs odin command inside the server container, its output is shown on the server page
+pub(crate) async fn odin_handler(
     State(state): State<SharedState>,
-    Path(name): Path<String>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((server_id, command)): Path<(String, OdinCommand)>,
+    Form(form): Form<OdinForm>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    {
-        let (mut backup_file_path, destination_path) = {
-            let state = state.read().await;
+    let (docker, server) = {
+        let state = state.read().await;
 
+        (
+            state.docker.clone(),
+            server_config(&state.config, &server_id)?,
+        )
+    };
+    let command_line = command
+        .command_line(form.message.as_deref())
+        .ok_or_else(|| {
             (
-                state.config.valheim_backups_path.clone(),
-                state.config.valheim_backups_destination_path.clone()
+                StatusCode::BAD_REQUEST,
+                "Notification message is required".to_string(),
             )
-        };
-        backup_file_path.push(name);
+        })?;
+
+    info!(
+        "User {} runs `{}` on server {}",
+        user.0,
+        command_line.join(" "),
+        server.id
+    );
 
-        backup::restore_backup(&backup_file_path, &destination_path).map_err(|e| {
-            error!("Failed restoring backup: {}", e);
+    let output = docker::exec_in_container(&docker, &server.container, &command_line)
+        .await
+        .map_err(|e| {
+            error!("Failed running odin command: {}", e);
 
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed restoring backup: {}", e),
+                format!("Failed running odin command: {}", e),
             )
         })?;
+    if !output.success() {
+        warn!(
+            "Odin command `{}` exited with code {:?}",
+            output.command, output.exit_code
+        );
     }
+    state
+        .write()
+        .await
+        .odin_outputs
+        .insert(server.id.clone(), output);
 
-    {
-        let mut state = state.write().await;
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
+}
 
-        docker::restart_container(&state.docker.clone(), &state.config.container_name)
-            .await
-            .map_err(|e| {
-                error!("Failed restarting container: {}", e);
+pub(crate) async fn environment_handler(
+    State(state): State<SharedState>,
+    Path(server_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let (docker, config, server, templates) = {
+        let state = state.read().await;
 
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed restarting container: {}", e),
-                )
-            })?;
+        (
+            state.docker.clone(),
+            state.config.clone(),
+            server_config(&state.config, &server_id)?,
+            state.templates.clone(),
+        )
+    };
 
-        state.last_restart_time = Some(Local::now().naive_local());
-    }
+    let env = retrieve_env(&docker, &server).await?;
 
-    Ok(Redirect::to("/"))
+    render_environment_page(&templates, &config, &server, env, vec![], render_start)
 }
 
-pub(crate) async fn static_path(Path(path): Path<String>) -> impl IntoResponse {
-    let path = path.trim_start_matches('/');
-    let mime_type = mime_guess::from_path(path).first_or_text_plain();
+/// Validates submitted environment variables and recreates the container with the changed ones,
+/// taking a backup first. Invalid submission is shown again with validation errors.
+pub(crate) async fn environment_apply_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<AxumResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let (docker, config, server, templates) = {
+        let state = state.read().await;
 
-    match STATIC_DIR.get_file(path) {
-        None => Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(body::boxed(Empty::new()))
-            .unwrap(),
-        Some(file) => Response::builder()
-            .status(StatusCode::OK)
-            .header(
-                header::CONTENT_TYPE,
-                HeaderValue::from_str(mime_type.as_ref()).unwrap(),
+        (
+            state.docker.clone(),
+            state.config.clone(),
+            server_config(&state.config, &server_id)?,
+            state.templates.clone(),
+        )
+    };
+
+    let current_env = retrieve_env(&docker, &server).await?;
+
+    let env_changes = match environment::collect_changes(&form, &current_env) {
+        Ok(env_changes) => env_changes,
+        Err(errors) => {
+            let mut values = current_env;
+            values.extend(
+                form.into_iter()
+                    .filter(|(key, _)| environment::editable_env_var(key).is_some()),
+            );
+
+            return render_environment_page(
+                &templates,
+                &config,
+                &server,
+                values,
+                errors,
+                render_start,
             )
-            .body(body::boxed(Full::from(file.contents())))
-            .unwrap(),
+            .map(|page| (StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+        }
+    };
+    if env_changes.is_empty() {
+        return Ok(Redirect::to(&format!("/servers/{}/environment", server.id)).into_response());
     }
+
+    apply_env_changes(&state, &docker, &server, user, &env_changes).await?;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)).into_response())
+}
+
+/// Takes a backup and recreates the container with changed environment variables
+async fn apply_env_changes(
+    state: &SharedState,
+    docker: &Docker,
+    server: &ServerConfig,
+    user: AuthenticatedUser,
+    env_changes: &BTreeMap<String, String>,
+) -> Result<(), (StatusCode, String)> {
+    let container = docker::ensure_recreatable(&server.container)
+        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+
+    create_backup(state, server).await.map_err(|e| {
+        error!("Failed creating backup before applying environment: {}", e);
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed creating backup before applying environment: {}", e),
+        )
+    })?;
+
+    info!(
+        "User {} changes environment variables {} of server {}",
+        user.0,
+        env_changes.keys().cloned().collect::<Vec<_>>().join(", "),
+        server.id
+    );
+
+    docker::recreate_container(docker, container, env_changes)
+        .await
+        .map_err(|e| {
+            error!("Failed recreating container: {}", e);
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed recreating container: {}", e),
+            )
+        })?;
+
+    record_restart(state, server, RestartTrigger::Reconfigure, Some(user.0)).await;
+
+    Ok(())
+}
+
+pub(crate) async fn settings_handler(
+    State(state): State<SharedState>,
+    Path(server_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let (docker, config, server, templates) = {
+        let state = state.read().await;
+
+        (
+            state.docker.clone(),
+            state.config.clone(),
+            server_config(&state.config, &server_id)?,
+            state.templates.clone(),
+        )
+    };
+
+    let current_env = retrieve_env(&docker, &server).await?;
+
+    render_settings_page(
+        &templates,
+        &config,
+        &server,
+        ServerSettings::from_env(&current_env),
+        vec![],
+        render_start,
+    )
 }
 
-#[inline]
-fn render_template(template_str: &str, replace_map: &HashMap<String, String>) -> String {
-    let mut replaced_str = String::from(template_str);
-    for (key, val) in replace_map {
-        replaced_str = replaced_str.replace(key, val);
+/// Validates submitted settings against Valheim rules and applies the changed ones
+pub(crate) async fn settings_apply_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
+    Form(settings): Form<ServerSettings>,
+) -> Result<AxumResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let (docker, config, server, templates) = {
+        let state = state.read().await;
+
+        (
+            state.docker.clone(),
+            state.config.clone(),
+            server_config(&state.config, &server_id)?,
+            state.templates.clone(),
+        )
+    };
+
+    let current_env = retrieve_env(&docker, &server).await?;
+    let errors = settings.validate(current_env.get("PASSWORD").map_or("", String::as_str));
+    if !errors.is_empty() {
+        return render_settings_page(&templates, &config, &server, settings, errors, render_start)
+            .map(|page| (StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+    }
+
+    let env_changes = settings.env_changes(&current_env);
+    if env_changes.is_empty() {
+        return Ok(Redirect::to(&format!("/servers/{}/settings", server.id)).into_response());
     }
 
-    replaced_str
+    apply_env_changes(&state, &docker, &server, user, &env_changes).await?;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)).into_response())
+}
+
+async fn retrieve_env(
+    docker: &Docker,
+    server: &ServerConfig,
+) -> Result<BTreeMap<String, String>, (StatusCode, String)> {
+    docker::retrieve_container_env(docker, &server.container)
+        .await
+        .map(|env| environment::parse_env(&env))
+        .map_err(|e| {
+            error!("Failed retrieving container environment: {}", e);
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed retrieving container environment: {}", e),
+            )
+        })
+}
+
+fn render_settings_page(
+    templates: &Templates,
+    config: &Config,
+    server: &ServerConfig,
+    settings: ServerSettings,
+    errors: Vec<String>,
+    render_start: SystemTime,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let page = templates
+        .render(
+            "settings.html",
+            context! {
+                version => version_with_build_time(),
+                servers => config.servers,
+                server,
+                settings,
+                presets => WorldPreset::ALL,
+                errors,
+                render_time => render_start.elapsed().unwrap().as_millis(),
+            },
+        )
+        .map_err(|e| {
+            error!("Failed rendering page: {}", e);
+
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    Ok(Html(page))
+}
+
+fn render_environment_page(
+    templates: &Templates,
+    config: &Config,
+    server: &ServerConfig,
+    mut values: BTreeMap<String, String>,
+    errors: Vec<String>,
+    render_start: SystemTime,
+) -> Result<Html<String>, (StatusCode, String)> {
+    for var in &EDITABLE_ENV_VARS {
+        if var.kind == EnvVarKind::Secret {
+            values.remove(var.name);
+        }
+    }
+
+    let page = templates
+        .render(
+            "environment.html",
+            context! {
+                version => version_with_build_time(),
+                servers => config.servers,
+                server,
+                vars => EDITABLE_ENV_VARS,
+                values,
+                errors,
+                render_time => render_start.elapsed().unwrap().as_millis(),
+            },
+        )
+        .map_err(|e| {
+            error!("Failed rendering page: {}", e);
+
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    Ok(Html(page))
+}
+
+pub(crate) async fn worlds_handler(
+    State(state): State<SharedState>,
+    Path(server_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let (docker, config, server, templates) = {
+        let state = state.read().await;
+
+        (
+            state.docker.clone(),
+            state.config.clone(),
+            server_config(&state.config, &server_id)?,
+            state.templates.clone(),
+        )
+    };
+
+    let worlds_path = worlds::worlds_path(&server.valheim_backups_destination_path);
+    let worlds = worlds::list_worlds(&worlds_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // Worlds are still listed when the container cannot be inspected, only active one is unknown
+    let active_world = match retrieve_env(&docker, &server).await {
+        Ok(mut env) => env.remove("WORLD"),
+        Err(_) => None,
+    };
+
+    let page = templates
+        .render(
+            "worlds.html",
+            context! {
+                version => version_with_build_time(),
+                servers => config.servers,
+                server,
+                worlds,
+                worlds_path,
+                active_world,
+                upload_max_size => humansize::format_size(WORLD_UPLOAD_MAX_SIZE, humansize::DECIMAL),
+                render_time => render_start.elapsed().unwrap().as_millis(),
+            },
+        )
+        .map_err(|e| {
+            error!("Failed rendering page: {}", e);
+
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    Ok(Html(page))
+}
+
+pub(crate) async fn world_download_handler(
+    State(state): State<SharedState>,
+    Path((server_id, name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let server = server_config(&state.read().await.config, &server_id)?;
+    let worlds_path = worlds::worlds_path(&server.valheim_backups_destination_path);
+    worlds::world_files(&worlds_path, &name).map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    // Archive is streamed as it is written, failure aborts the response instead of ending it
+    let (sender, receiver) = mpsc::channel(WORLD_DOWNLOAD_CHANNEL_CAPACITY);
+    let world_name = name.clone();
+    tokio::task::spawn_blocking(move || {
+        let writer =
+            BufWriter::with_capacity(WORLD_DOWNLOAD_CHUNK_SIZE, ChannelWriter(sender.clone()));
+        if let Err(e) = worlds::archive_world(&worlds_path, &world_name, writer) {
+            error!("Failed archiving world {}: {}", world_name, e);
+
+            let _ = sender.blocking_send(Err(io::Error::other(e.to_string())));
+        }
+    });
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/gzip"),
+        )
+        .header(
+            header::CONTENT_DISPOSITION,
+            HeaderValue::from_str(&format!("attachment; filename=\"{}.tar.gz\"", name)).unwrap(),
+        )
+        .body(StreamBody::new(stream))
+        .unwrap())
+}
+
+/// Stores world files uploaded from a local game, taking a backup first as files of an existing
+/// world get replaced. Files are streamed to hidden upload files, which are moved in place once
+/// the whole upload is validated.
+pub(crate) async fn world_upload_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (docker, server) = {
+        let state = state.read().await;
+
+        (
+            state.docker.clone(),
+            server_config(&state.config, &server_id)?,
+        )
+    };
+    let worlds_path = worlds::worlds_path(&server.valheim_backups_destination_path);
+    let active_world = retrieve_env(&docker, &server).await?.remove("WORLD");
+
+    let mut file_names = vec![];
+    let result = async {
+        tokio::fs::create_dir_all(&worlds_path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        let mut size = 0;
+        while let Some(mut field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| (e.status(), e.body_text()))?
+        {
+            let Some(file_name) = field.file_name().map(str::to_string) else {
+                continue;
+            };
+            if file_name.is_empty() {
+                continue;
+            }
+            let world_name =
+                worlds::validate_upload_file_name(&file_name, &file_names).map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Failed uploading world: {}", e),
+                    )
+                })?;
+            worlds::ensure_not_active(world_name, active_world.as_deref()).map_err(|e| {
+                (
+                    StatusCode::CONFLICT,
+                    format!("Failed uploading world: {}", e),
+                )
+            })?;
+
+            let mut file = File::create(worlds::upload_path(&worlds_path, &file_name))
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            file_names.push(file_name);
+            while let Some(chunk) = field
+                .chunk()
+                .await
+                .map_err(|e| (e.status(), e.body_text()))?
+            {
+                size += chunk.len();
+                if size > WORLD_UPLOAD_MAX_SIZE {
+                    return Err((
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        format!(
+                            "World is larger than {}",
+                            humansize::format_size(WORLD_UPLOAD_MAX_SIZE, humansize::DECIMAL)
+                        ),
+                    ));
+                }
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            }
+            file.sync_all()
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+
+        worlds::validate_upload(&file_names).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Failed uploading world: {}", e),
+            )
+        })?;
+        create_backup(&state, &server).await.map_err(|e| {
+            error!("Failed creating backup before uploading world: {}", e);
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed creating backup before uploading world: {}", e),
+            )
+        })?;
+
+        let imported_path = worlds_path.clone();
+        let imported_names = file_names.clone();
+        tokio::task::spawn_blocking(move || worlds::import_world(&imported_path, &imported_names))
+            .await
+            .expect("world upload task")
+            .map_err(|e| {
+                error!("Failed uploading world: {}", e);
+
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed uploading world: {}", e),
+                )
+            })
+    }
+    .await;
+
+    let world_name = match result {
+        Ok(world_name) => world_name,
+        Err(e) => {
+            for file_name in &file_names {
+                let _ = tokio::fs::remove_file(worlds::upload_path(&worlds_path, file_name)).await;
+            }
+
+            return Err(e);
+        }
+    };
+
+    info!(
+        "User {} uploaded world {} to server {}",
+        user.0, world_name, server.id
+    );
+
+    Ok(Redirect::to(&format!("/servers/{}/worlds", server.id)))
+}
+
+/// Makes the world active by changing `WORLD` environment variable of the container
+pub(crate) async fn world_activate_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((server_id, name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (docker, server) = {
+        let state = state.read().await;
+
+        (
+            state.docker.clone(),
+            server_config(&state.config, &server_id)?,
+        )
+    };
+
+    let worlds_path = worlds::worlds_path(&server.valheim_backups_destination_path);
+    let world_exists = worlds::list_worlds(&worlds_path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .iter()
+        .any(|world| world.name == name && world.complete);
+    if !world_exists {
+        return Err((StatusCode::NOT_FOUND, format!("World {} not found", name)));
+    }
+
+    let env_changes = BTreeMap::from([("WORLD".to_string(), name)]);
+    apply_env_changes(&state, &docker, &server, user, &env_changes).await?;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
+}
+
+pub(crate) async fn backups_handler(
+    State(state): State<SharedState>,
+    Path((server_id, name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let backup_file_path = {
+        let state = state.read().await;
+
+        backup_file_path(&server_config(&state.config, &server_id)?, &name)?
+    };
+
+    let mime_type = mime_guess::from_path(&backup_file_path).first_or_text_plain();
+
+    let file = match File::open(&backup_file_path).await {
+        Ok(file) => file,
+        Err(err) => return Err((StatusCode::NOT_FOUND, format!("File not found: {}", err))),
+    };
+    let stream = ReaderStream::new(file);
+    let body = StreamBody::new(stream);
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_str(mime_type.as_ref()).unwrap(),
+        )
+        .body(body)
+        .unwrap())
+}
+
+pub(crate) async fn backup_browse_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((server_id, name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let server = server_config(&state.read().await.config, &server_id)?;
+
+    render_backup_page(&state, &user, &server, &name, vec![], None, render_start).await
+}
+
+/// Restores files selected on backup page and restarts the container, or only shows which files
+/// would be overwritten when `dry_run` is submitted
+pub(crate) async fn backup_browse_restore_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((server_id, name)): Path<(String, String)>,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<AxumResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let (docker, server) = {
+        let state = state.read().await;
+
+        (
+            state.docker.clone(),
+            server_config(&state.config, &server_id)?,
+        )
+    };
+
+    let dry_run = form.iter().any(|(key, _)| key == "dry_run");
+    let selected: Vec<String> = form
+        .into_iter()
+        .filter(|(key, _)| key == "entries")
+        .map(|(_, value)| value)
+        .collect();
+    if selected.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "No files selected for restore".to_string(),
+        ));
+    }
+
+    let backup_file_path = backup_file_path(&server, &name)?;
+    let destination_path = server.valheim_backups_destination_path.clone();
+    let paths = selected.clone();
+    let restored = tokio::task::spawn_blocking(move || {
+        backup::restore_backup_entries(&backup_file_path, &destination_path, &paths, dry_run)
+    })
+    .await
+    .expect("restore task")
+    .map_err(|e| {
+        error!("Failed restoring backup entries: {}", e);
+
+        let status = match &e {
+            ValmanError::Backup(e) if e.kind() == io::ErrorKind::NotFound => {
+                StatusCode::BAD_REQUEST
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, format!("Failed restoring backup entries: {}", e))
+    })?;
+
+    if dry_run {
+        return render_backup_page(
+            &state,
+            &user,
+            &server,
+            &name,
+            selected,
+            Some(restored),
+            render_start,
+        )
+        .await
+        .map(IntoResponse::into_response);
+    }
+
+    info!(
+        "User {} restored {} files of backup {} to server {}",
+        user.0,
+        restored.len(),
+        name,
+        server.id
+    );
+
+    docker::restart_container(&docker, &server.container)
+        .await
+        .map_err(|e| {
+            error!("Failed restarting container: {}", e);
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed restarting container: {}", e),
+            )
+        })?;
+
+    record_restart(&state, &server, RestartTrigger::Restore, Some(user.0)).await;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)).into_response())
+}
+
+async fn render_backup_page(
+    state: &SharedState,
+    user: &AuthenticatedUser,
+    server: &ServerConfig,
+    name: &str,
+    selected: Vec<String>,
+    dry_run: Option<Vec<backup::RestoredEntry>>,
+    render_start: SystemTime,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let backup_file_path = backup_file_path(server, name)?;
+    let contents =
+        tokio::task::spawn_blocking(move || backup::read_backup_contents(&backup_file_path))
+            .await
+            .expect("backup contents task")
+            .map_err(|e| {
+                let status = match &e {
+                    ValmanError::Backup(e) if e.kind() == io::ErrorKind::NotFound => {
+                        StatusCode::NOT_FOUND
+                    }
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+
+                (status, format!("Failed reading backup: {}", e))
+            })?;
+
+    let (config, record, templates) = {
+        let state = state.read().await;
+
+        (
+            state.config.clone(),
+            read_server_state(&state.store, &server.id)
+                .await?
+                .backups
+                .get(name)
+                .cloned(),
+            state.templates.clone(),
+        )
+    };
+
+    let page = templates
+        .render(
+            "backup.html",
+            context! {
+                version => version_with_build_time(),
+                servers => config.servers,
+                server,
+                name,
+                contents,
+                record,
+                can_delete => user.1 == UserRole::Admin,
+                selected,
+                dry_run,
+                render_time => render_start.elapsed().unwrap().as_millis(),
+            },
+        )
+        .map_err(|e| {
+            error!("Failed rendering page: {}", e);
+
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    Ok(Html(page))
+}
+
+/// Resolves backup file in backups directory, names reaching outside of it are rejected
+fn backup_file_path(server: &ServerConfig, name: &str) -> Result<PathBuf, (StatusCode, String)> {
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err((StatusCode::NOT_FOUND, format!("Backup {} not found", name)));
+    }
+
+    Ok(server.valheim_backups_path.join(name))
+}
+
+/// Lists all backups of the server, a page at a time
+pub(crate) async fn backups_page_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
+    Query(query): Query<BackupsQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    let (config, server, server_state, templates) = {
+        let state = state.read().await;
+
+        (
+            state.config.clone(),
+            server_config(&state.config, &server_id)?,
+            read_server_state(&state.store, &server_id).await?,
+            state.templates.clone(),
+        )
+    };
+
+    let from = parse_date_filter("from", query.from.as_deref())?;
+    let to = parse_date_filter("to", query.to.as_deref())?;
+
+    let mut backups = backup::list_backups(&server.valheim_backups_path).map_err(|e| {
+        error!("Failed listing backups: {}", e);
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed listing backups: {}", e),
+        )
+    })?;
+    filter_backups_by_date(&mut backups, from, to);
+    backup::sort_backups(&mut backups, query.sort, query.order == SortOrder::Desc);
+
+    let total_count = backups.len();
+    let total_size: u64 = backups.iter().map(|backup| backup.size).sum();
+    let (backups, page_number, pages) = backups_page(backups, query.page);
+
+    let page = templates
+        .render(
+            "backups.html",
+            context! {
+                version => version_with_build_time(),
+                servers => config.servers,
+                server,
+                backups,
+                backup_records => server_state.backups,
+                page => page_number,
+                pages,
+                total_count,
+                hr_total_size => humansize::format_size(total_size, humansize::DECIMAL),
+                sort => query.sort,
+                order => query.order,
+                from => query.from.unwrap_or_default(),
+                to => query.to.unwrap_or_default(),
+                can_delete => user.1 == UserRole::Admin,
+                render_time => render_start.elapsed().unwrap().as_millis(),
+            },
+        )
+        .map_err(|e| {
+            error!("Failed rendering page: {}", e);
+
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    Ok(Html(page))
+}
+
+/// Keeps backups created between `from` and `to` days, both days included
+fn filter_backups_by_date(
+    backups: &mut Vec<SimpleDirEntry>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) {
+    backups.retain(|backup| {
+        let date = backup.creation_time.date();
+
+        from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+    });
+}
+
+/// Returns requested page of backups, its number and count of pages. Page out of range is clamped
+/// to the first or the last one, there is always at least one page even without any backups.
+fn backups_page(
+    backups: Vec<SimpleDirEntry>,
+    page: Option<usize>,
+) -> (Vec<SimpleDirEntry>, usize, usize) {
+    let pages = backups.len().div_ceil(BACKUPS_PAGE_SIZE).max(1);
+    let page_number = page.unwrap_or(1).clamp(1, pages);
+    let backups = backups
+        .into_iter()
+        .skip((page_number - 1) * BACKUPS_PAGE_SIZE)
+        .take(BACKUPS_PAGE_SIZE)
+        .collect();
+
+    (backups, page_number, pages)
+}
+
+/// Random lowercase hex token, identifying pending confirmations and temporary files
+fn random_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("generating random token");
+
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses optional date of backups filter, empty value means no limit
+fn parse_date_filter(
+    key: &str,
+    value: Option<&str>,
+) -> Result<Option<NaiveDate>, (StatusCode, String)> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => NaiveDate::parse_from_str(value, DATE_INPUT_FORMAT)
+            .map(Some)
+            .map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid {} date {}, expected YYYY-MM-DD", key, value),
+                )
+            }),
+    }
+}
+
+/// Streams uploaded backup archive into backups directory. Archive is kept only when it is
+/// readable (in any supported format) and has world files in it.
+pub(crate) async fn backup_upload_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let server = server_config(&state.read().await.config, &server_id)?;
+
+    let mut field = multipart
+        .next_field()
+        .await
+        .map_err(|e| (e.status(), e.body_text()))?
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "No backup uploaded".to_string()))?;
+    let name = field.file_name().unwrap_or_default().to_string();
+    if name.starts_with('.')
+        || !BACKUP_UPLOAD_EXTENSIONS
+            .iter()
+            .any(|extension| name.ends_with(extension))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Invalid backup file name {}, expected one of {} archives",
+                name,
+                BACKUP_UPLOAD_EXTENSIONS.join(", ")
+            ),
+        ));
+    }
+    let backup_path = backup_file_path(&server, &name)?;
+    if backup_path.exists() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Backup {} already exists", name),
+        ));
+    }
+
+    // Upload goes to a hidden file first, so it is not listed (or restored) before it is validated.
+    // Random suffix keeps concurrent uploads of the same name from writing into one file.
+    let upload_path =
+        server
+            .valheim_backups_path
+            .join(format!(".{}.{}.upload", name, random_token()));
+    let result = async {
+        let mut file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&upload_path)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let mut size = 0;
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| (e.status(), e.body_text()))?
+        {
+            size += chunk.len();
+            if size > BACKUP_UPLOAD_MAX_SIZE {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!(
+                        "Backup is larger than {}",
+                        humansize::format_size(BACKUP_UPLOAD_MAX_SIZE, humansize::DECIMAL)
+                    ),
+                ));
+            }
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+        file.sync_all()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        let validated_path = upload_path.clone();
+        let (sha256, worlds) =
+            tokio::task::spawn_blocking(move || backup::validate_uploaded_backup(&validated_path))
+                .await
+                .expect("backup validation task")
+                .map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid backup {}: {}", name, e),
+                    )
+                })?;
+
+        // Linking fails instead of replacing a backup of the same name uploaded meanwhile
+        tokio::fs::hard_link(&upload_path, &backup_path)
+            .await
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => (
+                    StatusCode::CONFLICT,
+                    format!("Backup {} already exists", name),
+                ),
+                _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            })?;
+        let _ = tokio::fs::remove_file(&upload_path).await;
+
+        Ok((size, sha256, worlds))
+    }
+    .await;
+
+    let (size, sha256, worlds) = match result {
+        Ok(uploaded) => uploaded,
+        Err(e) => {
+            error!("Failed uploading backup {}: {}", name, e.1);
+            let _ = tokio::fs::remove_file(&upload_path).await;
+
+            return Err(e);
+        }
+    };
+
+    info!(
+        "User {} uploaded backup {} ({}, worlds {}) to server {}",
+        user.0,
+        name,
+        humansize::format_size(size, humansize::DECIMAL),
+        worlds.join(", "),
+        server.id
+    );
+
+    // Whole archive was read during validation, so the upload counts as verified
+    let (server_id, backup_name) = (server.id.clone(), name.clone());
+    update_store(&state, move |persistent_state| {
+        let server_state = persistent_state.server_mut(&server_id);
+        server_state.record_backup_checksum(&backup_name, sha256.clone());
+        server_state.record_backup_verification(&backup_name, Ok(sha256));
+    })
+    .await;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
+}
+
+/// Saves pinned flag, labels and note of the backup
+pub(crate) async fn backup_metadata_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((server_id, name)): Path<(String, String)>,
+    Form(form): Form<BackupMetadataForm>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let server = server_config(&state.read().await.config, &server_id)?;
+    if !backup_file_path(&server, &name)?.is_file() {
+        return Err((StatusCode::NOT_FOUND, format!("Backup {} not found", name)));
+    }
+
+    let mut labels: Vec<String> = vec![];
+    for label in form.labels.split(',').map(str::trim) {
+        if label.chars().count() > BACKUP_LABEL_MAX_LEN {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Label {} is longer than {} characters",
+                    label, BACKUP_LABEL_MAX_LEN
+                ),
+            ));
+        }
+        if !label.is_empty() && !labels.iter().any(|l| l == label) {
+            labels.push(label.to_string());
+        }
+    }
+    if labels.len() > BACKUP_LABELS_MAX_COUNT {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} labels are allowed", BACKUP_LABELS_MAX_COUNT),
+        ));
+    }
+    let note = form.note.trim();
+    if note.chars().count() > BACKUP_NOTE_MAX_LEN {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Note is longer than {} characters", BACKUP_NOTE_MAX_LEN),
+        ));
+    }
+
+    let store = state.read().await.store.clone();
+    store
+        .update(|persistent_state| {
+            let record = persistent_state
+                .server_mut(&server.id)
+                .backups
+                .entry(name.clone())
+                .or_default();
+            if record.pinned != form.pinned {
+                info!(
+                    "User {} {} backup {} of server {}",
+                    user.0,
+                    if form.pinned { "pinned" } else { "unpinned" },
+                    name,
+                    server.id
+                );
+            }
+            record.pinned = form.pinned;
+            record.labels = labels;
+            record.note = (!note.is_empty()).then(|| note.to_string());
+        })
+        .map_err(|e| {
+            error!("Failed saving persistent state: {}", e);
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed saving backup metadata: {}", e),
+            )
+        })?;
+
+    Ok(Redirect::to(&format!(
+        "/servers/{}/backups/browse/{}",
+        server.id,
+        utf8_percent_encode(&name, PATH_SEGMENT)
+    )))
+}
+
+/// Deletes selected backups. Submitted selection is first shown on confirmation page, backups are
+/// deleted once it is confirmed. Requires admin role, pinned backups are never deleted.
+pub(crate) async fn backups_delete_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(server_id): Path<String>,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<AxumResponse, (StatusCode, String)> {
+    let render_start = SystemTime::now();
+    if user.1 != UserRole::Admin {
+        warn!(
+            target: AUDIT_LOG_TARGET,
+            "User {} was denied deleting backups of server {}", user.0, server_id
+        );
+
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Deleting backups requires admin role".to_string(),
+        ));
+    }
+
+    let (config, server, server_state, templates) = {
+        let state = state.read().await;
+
+        (
+            state.config.clone(),
+            server_config(&state.config, &server_id)?,
+            read_server_state(&state.store, &server_id).await?,
+            state.templates.clone(),
+        )
+    };
+
+    let token = form
+        .iter()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.clone());
+    let mut names: Vec<String> = form
+        .into_iter()
+        .filter(|(key, _)| key == "backups")
+        .map(|(_, value)| value)
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    if names.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No backups selected".to_string()));
+    }
+
+    let mut backups = vec![];
+    for name in &names {
+        if server_state
+            .backups
+            .get(name)
+            .is_some_and(|record| record.pinned)
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Backup {} is pinned, unpin it before deleting", name),
+            ));
+        }
+        let path = backup_file_path(&server, name)?;
+        let size = match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => return Err((StatusCode::NOT_FOUND, format!("Backup {} not found", name))),
+        };
+
+        backups.push((name.clone(), path, size));
+    }
+
+    if let Some(token) = token {
+        let pending = state.write().await.pending_deletions.remove(&token);
+        let confirmed = pending.is_some_and(|pending| {
+            pending.username == user.0
+                && pending.server_id == server.id
+                && pending.backups == names
+                && pending.expires_at > Instant::now()
+        });
+        if !confirmed {
+            warn!(
+                target: AUDIT_LOG_TARGET,
+                "User {} submitted invalid deletion confirmation for backups of server {}",
+                user.0,
+                server.id
+            );
+
+            return Err((
+                StatusCode::FORBIDDEN,
+                "Deletion confirmation is invalid or expired, select backups again".to_string(),
+            ));
+        }
+    } else {
+        let token = random_token();
+        {
+            let mut state = state.write().await;
+
+            let now = Instant::now();
+            state
+                .pending_deletions
+                .retain(|_, pending| pending.expires_at > now);
+            state.pending_deletions.insert(
+                token.clone(),
+                PendingDeletion {
+                    username: user.0.clone(),
+                    server_id: server.id.clone(),
+                    backups: names,
+                    expires_at: now + DELETE_CONFIRMATION_TIMEOUT,
+                },
+            );
+        }
+
+        let total_size: u64 = backups.iter().map(|(_, _, size)| size).sum();
+        let backups: Vec<_> = backups
+            .iter()
+            .map(|(name, _, size)| {
+                context! {
+                    name,
+                    hr_size => humansize::format_size(*size, humansize::DECIMAL),
+                }
+            })
+            .collect();
+
+        let page = templates
+            .render(
+                "delete_backups.html",
+                context! {
+                    version => version_with_build_time(),
+                    servers => config.servers,
+                    server,
+                    backups,
+                    token,
+                    hr_total_size => humansize::format_size(total_size, humansize::DECIMAL),
+                    render_time => render_start.elapsed().unwrap().as_millis(),
+                },
+            )
+            .map_err(|e| {
+                error!("Failed rendering page: {}", e);
+
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            })?;
+
+        return Ok(Html(page).into_response());
+    }
+
+    let mut result = Ok(());
+    let mut deleted = vec![];
+    for (name, path, _) in backups {
+        match tokio::task::spawn_blocking(move || backup::delete_backup(&path))
+            .await
+            .expect("delete task")
+        {
+            Ok(size) => {
+                info!(
+                    target: AUDIT_LOG_TARGET,
+                    "User {} deleted backup {} ({}) of server {}",
+                    user.0,
+                    name,
+                    humansize::format_size(size, humansize::DECIMAL),
+                    server.id
+                );
+                deleted.push(name);
+            }
+            Err(e) => {
+                error!(
+                    target: AUDIT_LOG_TARGET,
+                    "User {} failed deleting backup {} of server {}: {}", user.0, name, server.id, e
+                );
+                result = Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed deleting backup {}: {}", name, e),
+                ));
+                break;
+            }
+        }
+    }
+
+    let server_id = server.id.clone();
+    update_store(&state, move |persistent_state| {
+        let server_state = persistent_state.server_mut(&server_id);
+        for name in &deleted {
+            server_state.backups.remove(name);
+        }
+    })
+    .await;
+    result?;
+
+    Ok(Redirect::to(&format!("/servers/{}/backups", server.id)).into_response())
+}
+
+/// Reads the whole backup to check it is not corrupted and records the result
+pub(crate) async fn backup_verify_handler(
+    State(state): State<SharedState>,
+    Path((server_id, name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let server = server_config(&state.read().await.config, &server_id)?;
+    let backup_file_path = backup_file_path(&server, &name)?;
+    if !backup_file_path.is_file() {
+        return Err((StatusCode::NOT_FOUND, format!("Backup {} not found", name)));
+    }
+
+    let result = tokio::task::spawn_blocking(move || backup::verify_backup(&backup_file_path))
+        .await
+        .expect("verify task");
+    match &result {
+        Ok(_) => info!("Backup {} of server {} verified", name, server.id),
+        Err(e) => warn!(
+            "Backup {} of server {} failed verification: {}",
+            name, server.id, e
+        ),
+    }
+
+    let (server_id, backup_name) = (server.id.clone(), name);
+    update_store(&state, move |persistent_state| {
+        persistent_state
+            .server_mut(&server_id)
+            .record_backup_verification(&backup_name, result)
+    })
+    .await;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
+}
+
+pub(crate) async fn backups_restore_handler(
+    State(state): State<SharedState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((server_id, name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let server = server_config(&state.read().await.config, &server_id)?;
+
+    let backup_file_path = backup_file_path(&server, &name)?;
+    let destination_path = server.valheim_backups_destination_path.clone();
+    tokio::task::spawn_blocking(move || {
+        backup::restore_backup(&backup_file_path, &destination_path)
+    })
+    .await
+    .expect("restore task")
+    .map_err(|e| {
+        error!("Failed restoring backup: {}", e);
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed restoring backup: {}", e),
+        )
+    })?;
+
+    {
+        let state = state.read().await;
+
+        docker::restart_container(&state.docker, &server.container)
+            .await
+            .map_err(|e| {
+                error!("Failed restarting container: {}", e);
+
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed restarting container: {}", e),
+                )
+            })?;
+    }
+
+    record_restart(&state, &server, RestartTrigger::Restore, Some(user.0)).await;
+
+    Ok(Redirect::to(&format!("/servers/{}", server.id)))
+}
+
+/// Creates backup of the server saves and records its checksum in persistent state
+async fn create_backup(state: &SharedState, server: &ServerConfig) -> Result<PathBuf, ValmanError> {
+    let source_path = server.valheim_backups_destination_path.clone();
+    let backups_path = server.valheim_backups_path.clone();
+    let compression = server.backup_compression;
+    let (backup_path, sha256) = tokio::task::spawn_blocking(move || {
+        let backup_path = backup::create_backup(&source_path, &backups_path, compression)?;
+        let sha256 = backup::backup_checksum(&backup_path)?;
+
+        Ok::<_, ValmanError>((backup_path, sha256))
+    })
+    .await
+    .expect("backup task")?;
+
+    let (server_id, backup_name) = (server.id.clone(), backup::backup_name(&backup_path));
+    update_store(state, move |persistent_state| {
+        persistent_state
+            .server_mut(&server_id)
+            .record_backup_checksum(&backup_name, sha256)
+    })
+    .await;
+
+    Ok(backup_path)
+}
+
+/// Stores restart entry in persistent state and spawns a task measuring how long
+/// it takes the server to answer A2S queries again.
+pub(crate) async fn record_restart(
+    state: &SharedState,
+    server: &ServerConfig,
+    trigger: RestartTrigger,
+    triggered_by: Option<String>,
+) {
+    let restart_time = Local::now().naive_local();
+    let server_id = server.id.clone();
+    let server_address = server.valheim_server_address;
+
+    let restarted_server_id = server_id.clone();
+    update_store(state, move |persistent_state| {
+        persistent_state
+            .server_mut(&restarted_server_id)
+            .record_restart(RestartEntry {
+                time: restart_time,
+                trigger,
+                triggered_by,
+                recovery_seconds: None,
+            })
+    })
+    .await;
+    let a2s_client = state.read().await.a2s_client.clone();
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        let recovery_time = valve::wait_for_server(
+            &a2s_client,
+            &server_address,
+            RESTART_RECOVERY_POLL_INTERVAL,
+            RESTART_RECOVERY_TIMEOUT,
+        )
+        .await;
+
+        match recovery_time {
+            Some(recovery_time) => {
+                info!(
+                    "Server {} answered A2S query {}s after restart",
+                    server_id,
+                    recovery_time.as_secs()
+                );
+
+                update_store(&state, move |persistent_state| {
+                    persistent_state
+                        .server_mut(&server_id)
+                        .set_restart_recovery(restart_time, recovery_time.as_secs())
+                })
+                .await;
+            }
+            None => warn!(
+                "Server {} did not answer A2S query within {}s after restart",
+                server_id,
+                RESTART_RECOVERY_TIMEOUT.as_secs()
+            ),
+        }
+    });
+}
+
+/// Reads persistent state of the server, which can be changed by CLI commands at any time
+async fn read_server_state(
+    store: &Store,
+    server_id: &str,
+) -> Result<ServerState, (StatusCode, String)> {
+    let store = store.clone();
+    let server_id = server_id.to_string();
+
+    // Store locks and reads its file, which must not block the runtime
+    tokio::task::spawn_blocking(move || store.server(&server_id))
+        .await
+        .expect("store task")
+        .map_err(|e| {
+            error!("Failed reading persistent state: {}", e);
+
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/// Saves change of persistent state, failures are only logged as the change is not essential
+async fn update_store(
+    state: &SharedState,
+    update: impl FnOnce(&mut PersistentState) + Send + 'static,
+) {
+    let store = state.read().await.store.clone();
+
+    let result = tokio::task::spawn_blocking(move || store.update(update))
+        .await
+        .expect("store task");
+    if let Err(e) = result {
+        error!("Failed saving persistent state: {}", e);
+    }
+}
+
+pub(crate) async fn static_path(Path(path): Path<String>) -> impl IntoResponse {
+    let path = path.trim_start_matches('/');
+    let mime_type = mime_guess::from_path(path).first_or_text_plain();
+
+    match STATIC_DIR.get_file(path) {
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(body::boxed(Empty::new()))
+            .unwrap(),
+        Some(file) => Response::builder()
+            .status(StatusCode::OK)
+            .header(
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(mime_type.as_ref()).unwrap(),
+            )
+            .body(body::boxed(Full::from(file.contents())))
+            .unwrap(),
+    }
+}
+
+fn server_config(config: &Config, server_id: &str) -> Result<ServerConfig, (StatusCode, String)> {
+    config.server(server_id).cloned().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Unknown server {}", server_id),
+        )
+    })
+}
+
+fn version_with_build_time() -> String {
+    let build_timestamp: DateTime<Local> = DateTime::from(
+        DateTime::parse_from_rfc3339(env!("VERGEN_BUILD_TIMESTAMP"))
+            .expect("parse build timestamp"),
+    );
+
+    format!(
+        "{} (built {})",
+        version_with_commit(),
+        build_timestamp.format("%Y-%m-%d %H:%M:%S")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use chrono::NaiveDateTime;
+    use docker_api::Docker;
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::{config::DEFAULT_SERVER_ID, events, store::Store, AppState};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn backup(name: &str, creation_time: NaiveDateTime) -> SimpleDirEntry {
+        SimpleDirEntry {
+            name: name.to_string(),
+            creation_time,
+            size: 0,
+            hr_size: String::new(),
+        }
+    }
+
+    fn backups(count: usize) -> Vec<SimpleDirEntry> {
+        (0..count)
+            .map(|i| backup(&format!("{:03}", i), date(1).and_hms_opt(0, 0, 0).unwrap()))
+            .collect()
+    }
+
+    fn names(backups: &[SimpleDirEntry]) -> Vec<&str> {
+        backups.iter().map(|backup| backup.name.as_str()).collect()
+    }
+
+    #[test]
+    fn date_filter_includes_both_days() {
+        let mut backups = vec![
+            backup("before", date(1).and_hms_opt(23, 59, 59).unwrap()),
+            backup("first", date(2).and_hms_opt(0, 0, 0).unwrap()),
+            backup("last", date(3).and_hms_opt(23, 59, 59).unwrap()),
+            backup("after", date(4).and_hms_opt(0, 0, 0).unwrap()),
+        ];
+
+        filter_backups_by_date(&mut backups, Some(date(2)), Some(date(3)));
+
+        assert_eq!(names(&backups), ["first", "last"]);
+    }
+
+    #[test]
+    fn date_filter_without_limits_keeps_all_backups() {
+        let mut backups = backups(3);
+
+        filter_backups_by_date(&mut backups, None, None);
+
+        assert_eq!(backups.len(), 3);
+    }
+
+    #[test]
+    fn date_filter_values_are_parsed() {
+        assert_eq!(parse_date_filter("from", None).unwrap(), None);
+        assert_eq!(parse_date_filter("from", Some("")).unwrap(), None);
+        assert_eq!(parse_date_filter("from", Some("  ")).unwrap(), None);
+        assert_eq!(
+            parse_date_filter("to", Some("2024-01-03")).unwrap(),
+            Some(date(3))
+        );
+
+        for value in ["2024-02-30", "03.01.2024", "yesterday"] {
+            let (status, _) = parse_date_filter("to", Some(value)).unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn pages_are_cut_from_backups() {
+        let (page, page_number, pages) = backups_page(backups(BACKUPS_PAGE_SIZE * 2 + 1), Some(2));
+
+        assert_eq!((page_number, pages), (2, 3));
+        assert_eq!(page.len(), BACKUPS_PAGE_SIZE);
+        assert_eq!(page[0].name, format!("{:03}", BACKUPS_PAGE_SIZE));
+
+        let (page, page_number, _) = backups_page(backups(BACKUPS_PAGE_SIZE * 2 + 1), Some(3));
+        assert_eq!(page_number, 3);
+        assert_eq!(names(&page), [format!("{:03}", BACKUPS_PAGE_SIZE * 2)]);
+    }
+
+    #[test]
+    fn out_of_range_page_is_clamped() {
+        let (page, page_number, pages) = backups_page(backups(BACKUPS_PAGE_SIZE + 1), Some(5));
+        assert_eq!((page_number, pages), (2, 2));
+        assert_eq!(page.len(), 1);
+
+        let (page, page_number, _) = backups_page(backups(BACKUPS_PAGE_SIZE + 1), Some(0));
+        assert_eq!(page_number, 1);
+        assert_eq!(page.len(), BACKUPS_PAGE_SIZE);
+    }
+
+    #[test]
+    fn no_backups_give_single_empty_page() {
+        let (page, page_number, pages) = backups_page(vec![], Some(3));
+
+        assert!(page.is_empty());
+        assert_eq!((page_number, pages), (1, 1));
+    }
+
+    /// App state of single server with backups `a.tar.gz` and `b.tar.gz`, Docker is never contacted
+    async fn backups_state(dir: &std::path::Path) -> SharedState {
+        let backups_path = dir.join("backups");
+        fs::create_dir_all(&backups_path).unwrap();
+        fs::write(backups_path.join("a.tar.gz"), "a").unwrap();
+        fs::write(backups_path.join("b.tar.gz"), "b").unwrap();
+        let config = Config::from_file_contents(&format!(
+            r#"
+container_name = "valheim"
+valheim_backups_path = "{}"
+valheim_backups_destination_path = "{}"
+data_path = "{}"
+username = "admin"
+password = "secret"
+"#,
+            backups_path.display(),
+            dir.join("saves").display(),
+            dir.join("data").display(),
+        ));
+
+        Arc::new(RwLock::new(AppState {
+            docker: Arc::new(Docker::unix("/nonexistent/docker.sock")),
+            a2s_client: Arc::new(a2s::A2SClient::new().await.unwrap()),
+            store: Store::load(&config.data_path).unwrap(),
+            templates: Arc::new(Templates::new(&config.template_path).unwrap()),
+            config: Arc::new(config),
+            config_reload_error: None,
+            container_statuses: HashMap::new(),
+            status_updates: events::status_updates_channel(),
+            image_statuses: HashMap::new(),
+            odin_outputs: HashMap::new(),
+            pending_deletions: HashMap::new(),
+        }))
+    }
+
+    async fn delete(
+        state: &SharedState,
+        role: UserRole,
+        form: &[(&str, &str)],
+    ) -> Result<AxumResponse, (StatusCode, String)> {
+        backups_delete_handler(
+            State(state.clone()),
+            Extension(AuthenticatedUser("admin".to_string(), role)),
+            Path(DEFAULT_SERVER_ID.to_string()),
+            Form(
+                form.iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            ),
+        )
+        .await
+    }
+
+    async fn pending_token(state: &SharedState) -> String {
+        let state = state.read().await;
+        assert_eq!(state.pending_deletions.len(), 1);
+
+        state.pending_deletions.keys().next().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn backups_are_deleted_after_confirmation() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = backups_state(dir.path()).await;
+        let backup_path = dir.path().join("backups/a.tar.gz");
+
+        let response = delete(&state, UserRole::Admin, &[("backups", "a.tar.gz")])
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(backup_path.exists());
+
+        let token = pending_token(&state).await;
+        let response = delete(
+            &state,
+            UserRole::Admin,
+            &[("backups", "a.tar.gz"), ("token", &token)],
+        )
+        .await
+        .unwrap();
+        assert!(response.status().is_redirection());
+        assert!(!backup_path.exists());
+        assert!(dir.path().join("backups/b.tar.gz").exists());
+        assert!(state.read().await.pending_deletions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deletion_without_valid_token_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = backups_state(dir.path()).await;
+
+        let (status, _) = delete(
+            &state,
+            UserRole::Admin,
+            &[("backups", "a.tar.gz"), ("token", "forged")],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Token confirms only the selection it was shown for
+        delete(&state, UserRole::Admin, &[("backups", "a.tar.gz")])
+            .await
+            .unwrap();
+        let token = pending_token(&state).await;
+        let (status, _) = delete(
+            &state,
+            UserRole::Admin,
+            &[
+                ("backups", "a.tar.gz"),
+                ("backups", "b.tar.gz"),
+                ("token", &token),
+            ],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        assert!(dir.path().join("backups/a.tar.gz").exists());
+        assert!(dir.path().join("backups/b.tar.gz").exists());
+    }
+
+    #[tokio::test]
+    async fn pinned_backups_are_not_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = backups_state(dir.path()).await;
+        state
+            .read()
+            .await
+            .store
+            .update(|state| {
+                state
+                    .server_mut(DEFAULT_SERVER_ID)
+                    .backups
+                    .entry("a.tar.gz".to_string())
+                    .or_default()
+                    .pinned = true;
+            })
+            .unwrap();
+
+        let (status, _) = delete(&state, UserRole::Admin, &[("backups", "a.tar.gz")])
+            .await
+            .unwrap_err();
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(state.read().await.pending_deletions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn operators_cannot_delete_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = backups_state(dir.path()).await;
+
+        let (status, _) = delete(&state, UserRole::Operator, &[("backups", "a.tar.gz")])
+            .await
+            .unwrap_err();
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(state.read().await.pending_deletions.is_empty());
+        assert!(dir.path().join("backups/a.tar.gz").exists());
+    }
 }
@@ -1,43 +1,84 @@
-use axum::{extract::FromRef, middleware, routing::get, Router, Server};
-use chrono::NaiveDateTime;
+use axum::{
+    extract::{DefaultBodyLimit, FromRef},
+    middleware,
+    routing::{get, post},
+    Router, Server,
+};
+use clap::Parser;
 use docker_api::Docker;
 use include_dir::{include_dir, Dir};
-use log::{debug, info};
-use std::{fs, sync::Arc};
-use tokio::sync::RwLock;
+use log::info;
+use std::{collections::HashMap, process::ExitCode, sync::Arc};
+use tokio::sync::{broadcast, RwLock};
 
-use crate::config::Config;
+use crate::{
+    cli::{Cli, Command},
+    config::Config,
+    docker::{ContainerStatus, ExecOutput, ImageStatus},
+    events::StatusUpdate,
+    handlers::PendingDeletion,
+    store::Store,
+    templates::Templates,
+};
 
 mod backup;
+mod cli;
+mod commands;
 mod config;
 mod docker;
+mod environment;
 mod error;
+mod events;
 mod handlers;
+mod odin;
+mod settings;
+mod store;
+mod templates;
 mod valve;
+mod watcher;
+mod worlds;
 
 static STATIC_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/static");
+static TEMPLATES_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/templates");
 
 #[derive(Clone, FromRef)]
 struct AppState {
     docker: Arc<Docker>,
     a2s_client: Arc<a2s::A2SClient>,
-    last_restart_time: Option<NaiveDateTime>,
-    template: String,
+    store: Store,
+    templates: Arc<Templates>,
     config: Arc<Config>,
+    config_reload_error: Option<String>,
+    /// Latest container status of each server by server id, maintained from Docker events
+    container_statuses: HashMap<String, ContainerStatus>,
+    status_updates: broadcast::Sender<StatusUpdate>,
+    /// Result of the last image update check of each server by server id
+    image_statuses: HashMap<String, ImageStatus>,
+    /// Output of the last odin command run on each server by server id
+    odin_outputs: HashMap<String, ExecOutput>,
+    /// Backup deletions shown on confirmation page by confirmation token
+    pending_deletions: HashMap<String, PendingDeletion>,
 }
 
 type SharedState = Arc<RwLock<AppState>>;
 
-#[derive(Debug, Clone)]
-struct SimpleDirEntry {
-    name: String,
-    creation_time: NaiveDateTime,
-    hr_size: String,
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match &cli.command {
+        None | Some(Command::Serve) => {
+            serve(&cli).await;
+
+            ExitCode::SUCCESS
+        }
+        Some(Command::CheckConfig) => commands::check_config(&cli.config),
+        Some(command) => commands::run(&cli.config, cli.server.as_deref(), command).await,
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    log4rs::init_file("log4rs.yml", Default::default()).expect("logging initialization");
+async fn serve(cli: &Cli) {
+    log4rs::init_file(&cli.log_config, Default::default()).expect("logging initialization");
 
     info!(
         "Starting {} {}",
@@ -45,29 +86,109 @@ async fn main() {
         version_with_commit()
     );
 
-    let config = Arc::new(Config::new().expect("config initialization"));
+    let config = Arc::new(Config::new(&cli.config).expect("config initialization"));
 
-    let docker = Arc::new(Docker::unix(&config.docker_socket_path));
+    let docker = Arc::new(docker::connect(&config).expect("Docker client initialization"));
     let a2s_client = Arc::new(a2s::A2SClient::new().await.expect("creating A2S client"));
-    let template = fs::read_to_string(&config.template_path).expect("loading page template");
+    let templates =
+        Arc::new(Templates::new(&config.template_path).expect("loading page templates"));
+    let store = Store::load(&config.data_path).expect("loading persistent state");
 
     let shared_state: SharedState = Arc::new(RwLock::new(AppState {
         docker,
         a2s_client,
-        last_restart_time: None,
-        template,
+        store,
+        templates,
         config: config.clone(),
+        config_reload_error: None,
+        container_statuses: HashMap::new(),
+        status_updates: events::status_updates_channel(),
+        image_statuses: HashMap::new(),
+        odin_outputs: HashMap::new(),
+        pending_deletions: HashMap::new(),
     }));
 
+    watcher::spawn_templates_watcher(shared_state.clone(), &config.template_path);
+    watcher::spawn_config_watcher(shared_state.clone(), &cli.config);
+    events::spawn_docker_events_watcher(shared_state.clone());
+
     let app = Router::new()
         .route("/", get(handlers::root_handler))
-        .route("/restart", get(handlers::restart_handler))
-        .route("/backups/:name", get(handlers::backups_handler))
+        .route("/servers/:server_id", get(handlers::server_handler))
+        .route(
+            "/servers/:server_id/events",
+            get(handlers::server_events_handler),
+        )
+        .route(
+            "/servers/:server_id/restart",
+            post(handlers::restart_handler),
+        )
+        .route(
+            "/servers/:server_id/image/check",
+            post(handlers::image_check_handler),
+        )
+        .route("/servers/:server_id/update", post(handlers::update_handler))
+        .route(
+            "/servers/:server_id/environment",
+            get(handlers::environment_handler).post(handlers::environment_apply_handler),
+        )
+        .route(
+            "/servers/:server_id/settings",
+            get(handlers::settings_handler).post(handlers::settings_apply_handler),
+        )
+        .route(
+            "/servers/:server_id/odin/:command",
+            post(handlers::odin_handler),
+        )
+        .route("/servers/:server_id/worlds", get(handlers::worlds_handler))
+        .route(
+            "/servers/:server_id/worlds/upload",
+            post(handlers::world_upload_handler)
+                .layer(DefaultBodyLimit::max(handlers::WORLD_UPLOAD_MAX_SIZE)),
+        )
+        .route(
+            "/servers/:server_id/worlds/:name/download",
+            get(handlers::world_download_handler),
+        )
+        .route(
+            "/servers/:server_id/worlds/:name/activate",
+            post(handlers::world_activate_handler),
+        )
+        .route(
+            "/servers/:server_id/backups/:name",
+            get(handlers::backups_handler),
+        )
+        .route(
+            "/servers/:server_id/backups/browse/:name",
+            get(handlers::backup_browse_handler).post(handlers::backup_browse_restore_handler),
+        )
+        .route(
+            "/servers/:server_id/backups",
+            get(handlers::backups_page_handler),
+        )
+        .route(
+            "/servers/:server_id/backups/upload",
+            post(handlers::backup_upload_handler)
+                .layer(DefaultBodyLimit::max(handlers::BACKUP_UPLOAD_MAX_SIZE)),
+        )
+        .route(
+            "/servers/:server_id/backups/delete",
+            post(handlers::backups_delete_handler),
+        )
+        .route(
+            "/servers/:server_id/backups/metadata/:name",
+            post(handlers::backup_metadata_handler),
+        )
+        .route(
+            "/servers/:server_id/backups/verify/:name",
+            post(handlers::backup_verify_handler),
+        )
         .route(
-            "/backups/restore/:name",
-            get(handlers::backups_restore_handler),
+            "/servers/:server_id/backups/restore/:name",
+            post(handlers::backups_restore_handler),
         )
         .route("/static/*path", get(handlers::static_path))
+        .route_layer(middleware::from_fn(handlers::same_origin))
         .route_layer(middleware::from_fn_with_state(
             shared_state.clone(),
             handlers::auth,
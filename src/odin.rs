@@ -0,0 +1,46 @@
+use chrono::Local;
+use serde::Deserialize;
+
+/// `odin` CLI shipped in mbround18/valheim-docker images
+const ODIN_BINARY: &str = "odin";
+/// Valheim saves and backups locations inside valheim-docker container
+const CONTAINER_SAVES_PATH: &str = "/home/steam/.config/unity3d/IronGate/Valheim";
+const CONTAINER_BACKUPS_PATH: &str = "/home/steam/backups";
+
+/// Curated set of odin commands available as dashboard actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OdinCommand {
+    Status,
+    Backup,
+    UpdateCheck,
+    Notify,
+}
+
+impl OdinCommand {
+    /// Command line of the command, `None` when notification message is missing
+    pub fn command_line(self, message: Option<&str>) -> Option<Vec<String>> {
+        let args = match self {
+            OdinCommand::Status => vec!["status".to_string()],
+            OdinCommand::Backup => vec![
+                "backup".to_string(),
+                CONTAINER_SAVES_PATH.to_string(),
+                format!(
+                    "{}/{}",
+                    CONTAINER_BACKUPS_PATH,
+                    Local::now().format("valman-odin-backup-%Y%m%d-%H%M%S.tar.gz")
+                ),
+            ],
+            OdinCommand::UpdateCheck => vec!["update".to_string(), "--check".to_string()],
+            OdinCommand::Notify => vec![
+                "notify".to_string(),
+                message
+                    .map(str::trim)
+                    .filter(|message| !message.is_empty())?
+                    .to_string(),
+            ],
+        };
+
+        Some([vec![ODIN_BINARY.to_string()], args].concat())
+    }
+}
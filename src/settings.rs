@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Valheim world modifier presets, passed to the server as `-preset <name>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorldPreset {
+    Normal,
+    Casual,
+    Easy,
+    Hard,
+    Hardcore,
+    Immersive,
+    Hammer,
+}
+
+impl WorldPreset {
+    pub const ALL: [WorldPreset; 7] = [
+        WorldPreset::Normal,
+        WorldPreset::Casual,
+        WorldPreset::Easy,
+        WorldPreset::Hard,
+        WorldPreset::Hardcore,
+        WorldPreset::Immersive,
+        WorldPreset::Hammer,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WorldPreset::Normal => "normal",
+            WorldPreset::Casual => "casual",
+            WorldPreset::Easy => "easy",
+            WorldPreset::Hard => "hard",
+            WorldPreset::Hardcore => "hardcore",
+            WorldPreset::Immersive => "immersive",
+            WorldPreset::Hammer => "hammer",
+        }
+    }
+
+    fn from_env(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.as_str().eq_ignore_ascii_case(value.trim()))
+    }
+}
+
+/// Server settings stored in valheim-docker container environment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerSettings {
+    pub name: String,
+    pub world: String,
+    /// Empty keeps the current password
+    #[serde(default, skip_serializing)]
+    pub password: String,
+    /// Removes the current password, as an empty `password` keeps it
+    #[serde(default, deserialize_with = "deserialize_checkbox")]
+    pub clear_password: bool,
+    #[serde(default, deserialize_with = "deserialize_checkbox")]
+    pub public: bool,
+    #[serde(default, deserialize_with = "deserialize_checkbox")]
+    pub crossplay: bool,
+    #[serde(default, deserialize_with = "deserialize_preset")]
+    pub preset: Option<WorldPreset>,
+}
+
+/// HTML checkboxes are only submitted when checked
+pub(crate) fn deserialize_checkbox<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+
+    Ok(matches!(value.as_str(), "on" | "1" | "true"))
+}
+
+fn deserialize_preset<'de, D>(deserializer: D) -> Result<Option<WorldPreset>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    WorldPreset::from_env(&value)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown world preset {}", value)))
+}
+
+fn env_flag(env: &BTreeMap<String, String>, key: &str) -> bool {
+    matches!(env.get(key).map(String::as_str), Some("1" | "true"))
+}
+
+impl ServerSettings {
+    pub fn from_env(env: &BTreeMap<String, String>) -> Self {
+        Self {
+            name: env.get("NAME").cloned().unwrap_or_default(),
+            world: env.get("WORLD").cloned().unwrap_or_default(),
+            password: env.get("PASSWORD").cloned().unwrap_or_default(),
+            clear_password: false,
+            public: env_flag(env, "PUBLIC"),
+            crossplay: env_flag(env, "CROSSPLAY"),
+            preset: env
+                .get("PRESET")
+                .and_then(|preset| WorldPreset::from_env(preset)),
+        }
+    }
+
+    /// Checks the settings against rules the Valheim server enforces on startup.
+    /// `current_password` is used when the password is left unchanged.
+    pub fn validate(&self, current_password: &str) -> Vec<String> {
+        let mut errors = vec![];
+        let password = self.effective_password(current_password);
+
+        if self.name.trim().is_empty() {
+            errors.push("Server name is required".to_string());
+        }
+        if self.world.is_empty() {
+            errors.push("World name is required".to_string());
+        } else if !self
+            .world
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            errors.push(
+                "World name can contain only letters, digits, underscores and dashes".to_string(),
+            );
+        }
+        if [&self.name, &self.world, &self.password]
+            .iter()
+            .any(|value| value.contains(['\n', '\r', '\0']))
+        {
+            errors.push("Settings must be single line values".to_string());
+        }
+
+        if self.clear_password && !self.password.is_empty() {
+            errors.push("Password cannot be both changed and cleared".to_string());
+        } else if password.is_empty() {
+            if self.public {
+                errors.push("Public server requires a password".to_string());
+            }
+        } else {
+            if password.chars().count() < 5 {
+                errors.push("Password must be at least 5 characters long".to_string());
+            }
+            let name = self.name.trim().to_lowercase();
+            let lowercase_password = password.to_lowercase();
+            if !name.is_empty()
+                && (lowercase_password.contains(&name) || name.contains(&lowercase_password))
+            {
+                errors
+                    .push("Password must not contain the server name or be part of it".to_string());
+            }
+        }
+
+        errors
+    }
+
+    /// Environment variables differing from `current_env`
+    pub fn env_changes(&self, current_env: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let flag = |value: bool| if value { "1" } else { "0" }.to_string();
+        let current_password = current_env.get("PASSWORD").map_or("", String::as_str);
+        let current = Self::from_env(current_env);
+
+        let mut changes = BTreeMap::new();
+        if self.name.trim() != current.name {
+            changes.insert("NAME".to_string(), self.name.trim().to_string());
+        }
+        if self.world != current.world {
+            changes.insert("WORLD".to_string(), self.world.clone());
+        }
+        let password = self.effective_password(current_password);
+        if password != current_password {
+            changes.insert("PASSWORD".to_string(), password.to_string());
+        }
+        if self.public != current.public {
+            changes.insert("PUBLIC".to_string(), flag(self.public));
+        }
+        if self.crossplay != current.crossplay {
+            changes.insert("CROSSPLAY".to_string(), flag(self.crossplay));
+        }
+        if self.preset != current.preset {
+            changes.insert(
+                "PRESET".to_string(),
+                self.preset
+                    .map(WorldPreset::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+        }
+
+        changes
+    }
+
+    fn effective_password<'a>(&'a self, current_password: &'a str) -> &'a str {
+        if self.clear_password {
+            ""
+        } else if self.password.is_empty() {
+            current_password
+        } else {
+            &self.password
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(name: &str, password: &str) -> ServerSettings {
+        ServerSettings {
+            name: name.to_string(),
+            world: "Dedicated".to_string(),
+            password: password.to_string(),
+            clear_password: false,
+            public: true,
+            crossplay: false,
+            preset: None,
+        }
+    }
+
+    #[test]
+    fn valid_settings_pass() {
+        assert!(settings("My server", "Secret123").validate("").is_empty());
+    }
+
+    #[test]
+    fn password_must_not_contain_name_or_be_part_of_it() {
+        let expected = ["Password must not contain the server name or be part of it"];
+
+        assert_eq!(settings("Viking", "vikings123").validate(""), expected);
+        assert_eq!(
+            settings("Vikings of the north", "NORTH").validate(""),
+            expected
+        );
+        // Surrounding whitespace of the name is ignored, as it is trimmed when applied
+        assert_eq!(settings(" viking ", "Viking1").validate(""), expected);
+    }
+
+    #[test]
+    fn current_password_is_validated_when_left_unchanged() {
+        assert!(settings("Viking", "").validate("Secret123").is_empty());
+        assert_eq!(
+            settings("Viking", "").validate("viking1"),
+            ["Password must not contain the server name or be part of it"]
+        );
+        assert_eq!(
+            settings("Viking", "").validate(""),
+            ["Public server requires a password"]
+        );
+    }
+
+    #[test]
+    fn private_server_without_password_passes() {
+        let settings = ServerSettings {
+            public: false,
+            ..settings("Viking", "")
+        };
+
+        assert!(settings.validate("").is_empty());
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        assert_eq!(
+            settings("Viking", "abc").validate(""),
+            ["Password must be at least 5 characters long"]
+        );
+
+        let settings = ServerSettings {
+            world: "My world".to_string(),
+            ..settings(" ", "Secret\n123")
+        };
+        assert_eq!(
+            settings.validate(""),
+            [
+                "Server name is required",
+                "World name can contain only letters, digits, underscores and dashes",
+                "Settings must be single line values",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_changed_settings_are_applied() {
+        let current_env = BTreeMap::from([
+            ("NAME".to_string(), "Viking".to_string()),
+            ("WORLD".to_string(), "Dedicated".to_string()),
+            ("PASSWORD".to_string(), "Secret123".to_string()),
+            ("PUBLIC".to_string(), "true".to_string()),
+            ("PRESET".to_string(), "Hard".to_string()),
+        ]);
+        let settings = ServerSettings {
+            crossplay: true,
+            preset: None,
+            ..settings(" Viking ", "")
+        };
+
+        assert_eq!(
+            settings.env_changes(&current_env),
+            BTreeMap::from([
+                ("CROSSPLAY".to_string(), "1".to_string()),
+                ("PRESET".to_string(), String::new()),
+            ])
+        );
+    }
+
+    #[test]
+    fn cleared_password_is_validated_against_public_listing() {
+        let cleared = |public| ServerSettings {
+            clear_password: true,
+            public,
+            ..settings("Viking", "")
+        };
+
+        assert_eq!(
+            cleared(true).validate("Secret123"),
+            ["Public server requires a password"]
+        );
+        assert!(cleared(false).validate("Secret123").is_empty());
+        assert_eq!(
+            ServerSettings {
+                clear_password: true,
+                ..settings("Viking", "Secret123")
+            }
+            .validate(""),
+            ["Password cannot be both changed and cleared"]
+        );
+    }
+
+    #[test]
+    fn cleared_password_is_applied_as_empty() {
+        let current_env = BTreeMap::from([
+            ("NAME".to_string(), "Viking".to_string()),
+            ("WORLD".to_string(), "Dedicated".to_string()),
+            ("PASSWORD".to_string(), "Secret123".to_string()),
+        ]);
+        let settings = ServerSettings {
+            clear_password: true,
+            public: false,
+            ..settings("Viking", "")
+        };
+
+        assert_eq!(
+            settings.env_changes(&current_env),
+            BTreeMap::from([("PASSWORD".to_string(), String::new())])
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDateTime};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, ValmanError::Store as StoreError};
+
+const STATE_FILE_NAME: &str = "state.json";
+/// Locked while the state file is updated, the state file itself is replaced on every save
+const LOCK_FILE_NAME: &str = "state.json.lock";
+const MAX_RESTART_HISTORY_LEN: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartTrigger {
+    Manual,
+    Restore,
+    Update,
+    Reconfigure,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestartEntry {
+    pub time: NaiveDateTime,
+    pub trigger: RestartTrigger,
+    pub triggered_by: Option<String>,
+    /// Seconds it took the server to answer A2S queries again, `None` while pending or when it never did
+    pub recovery_seconds: Option<u64>,
+}
+
+/// Integrity information and user metadata of a backup file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupRecord {
+    /// SHA-256 of the backup file, computed when it was created or first verified
+    pub sha256: Option<String>,
+    /// Whether the last verification read the whole archive without errors
+    #[serde(default)]
+    pub verified: bool,
+    pub verified_at: Option<NaiveDateTime>,
+    pub verification_error: Option<String>,
+    /// Pinned backups are kept forever, cleanup never removes them
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PersistentState {
+    /// State of each managed server, by server id
+    #[serde(default)]
+    pub servers: HashMap<String, ServerState>,
+}
+
+impl PersistentState {
+    pub fn server(&self, server_id: &str) -> ServerState {
+        self.servers.get(server_id).cloned().unwrap_or_default()
+    }
+
+    pub fn server_mut(&mut self, server_id: &str) -> &mut ServerState {
+        self.servers.entry(server_id.to_string()).or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerState {
+    #[serde(default)]
+    pub last_restart_time: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub restart_history: Vec<RestartEntry>,
+    /// Backup records, by backup file name
+    #[serde(default)]
+    pub backups: HashMap<String, BackupRecord>,
+}
+
+impl ServerState {
+    /// Checks whether enough time passed since the last restart
+    pub fn restart_allowed(&self, restart_delay_seconds: u32) -> bool {
+        match self.last_restart_time {
+            Some(last_restart) => {
+                last_restart
+                    .signed_duration_since(Local::now().naive_local())
+                    .num_seconds()
+                    .abs()
+                    > restart_delay_seconds.into()
+            }
+            None => true,
+        }
+    }
+
+    pub fn record_restart(&mut self, entry: RestartEntry) {
+        self.last_restart_time = Some(entry.time);
+        self.restart_history.push(entry);
+
+        if self.restart_history.len() > MAX_RESTART_HISTORY_LEN {
+            let overflow = self.restart_history.len() - MAX_RESTART_HISTORY_LEN;
+            self.restart_history.drain(..overflow);
+        }
+    }
+
+    pub fn record_backup_checksum(&mut self, name: &str, sha256: String) {
+        self.backups.insert(
+            name.to_string(),
+            BackupRecord {
+                sha256: Some(sha256),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Records result of backup verification. Checksum differing from the recorded one fails
+    /// the verification even when the archive itself is readable.
+    pub fn record_backup_verification(&mut self, name: &str, result: Result<String>) {
+        let record = self.backups.entry(name.to_string()).or_default();
+        record.verified_at = Some(Local::now().naive_local());
+
+        let error = match result {
+            Ok(sha256) => match &record.sha256 {
+                Some(recorded) if *recorded != sha256 => Some(format!(
+                    "Checksum {} differs from {} recorded when the backup was created",
+                    sha256, recorded
+                )),
+                _ => {
+                    record.sha256 = Some(sha256);
+
+                    None
+                }
+            },
+            Err(e) => Some(e.to_string()),
+        };
+        record.verified = error.is_none();
+        record.verification_error = error;
+    }
+
+    pub fn set_restart_recovery(&mut self, restart_time: NaiveDateTime, recovery_seconds: u64) {
+        if let Some(entry) = self
+            .restart_history
+            .iter_mut()
+            .rev()
+            .find(|e| e.time == restart_time)
+        {
+            entry.recovery_seconds = Some(recovery_seconds);
+        }
+    }
+}
+
+/// Persistent state file shared by the web server and CLI commands, which can run at the same time.
+/// State is not cached, every read loads the file and every update re-reads it under an exclusive
+/// lock, so changes made by other processes are neither missed nor overwritten.
+#[derive(Debug, Clone)]
+pub struct Store {
+    path: PathBuf,
+    lock_path: PathBuf,
+}
+
+impl Store {
+    /// Opens the store in `data_path`, checking the state file can be read
+    pub fn load(data_path: &Path) -> Result<Self> {
+        let store = Self {
+            path: data_path.join(STATE_FILE_NAME),
+            lock_path: data_path.join(LOCK_FILE_NAME),
+        };
+
+        if store.path.exists() {
+            store.read()?;
+        } else {
+            info!(
+                "Persistent state file {} not found, starting with empty state",
+                store.path.display()
+            );
+        }
+
+        Ok(store)
+    }
+
+    pub fn read(&self) -> Result<PersistentState> {
+        if !self.path.exists() {
+            return Ok(PersistentState::default());
+        }
+
+        debug!("Loading persistent state from {}", self.path.display());
+
+        let contents = fs::read_to_string(&self.path).map_err(|e| StoreError(e.to_string()))?;
+        serde_json::from_str(&contents).map_err(|e| StoreError(e.to_string()))
+    }
+
+    pub fn server(&self, server_id: &str) -> Result<ServerState> {
+        self.read().map(|state| state.server(server_id))
+    }
+
+    /// Applies `update` to the current state and saves it, other processes wait until it is saved
+    pub fn update<T>(&self, update: impl FnOnce(&mut PersistentState) -> T) -> Result<T> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| StoreError(e.to_string()))?;
+        }
+
+        let lock_file = File::create(&self.lock_path).map_err(|e| StoreError(e.to_string()))?;
+        lock_file.lock().map_err(|e| StoreError(e.to_string()))?;
+
+        let mut state = self.read()?;
+        let result = update(&mut state);
+        self.save(&state)?;
+
+        Ok(result)
+    }
+
+    fn save(&self, state: &PersistentState) -> Result<()> {
+        debug!("Saving persistent state to {}", self.path.display());
+
+        let contents =
+            serde_json::to_string_pretty(state).map_err(|e| StoreError(e.to_string()))?;
+        // Write to a temporary file first so a crash never leaves a truncated state file behind
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents).map_err(|e| StoreError(e.to_string()))?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| StoreError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn time(minute: usize) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + chrono::Duration::minutes(minute as i64)
+    }
+
+    fn restart(minute: usize) -> RestartEntry {
+        RestartEntry {
+            time: time(minute),
+            trigger: RestartTrigger::Manual,
+            triggered_by: Some("admin".to_string()),
+            recovery_seconds: None,
+        }
+    }
+
+    #[test]
+    fn restart_history_keeps_newest_entries() {
+        let mut state = ServerState::default();
+
+        for minute in 0..MAX_RESTART_HISTORY_LEN + 3 {
+            state.record_restart(restart(minute));
+        }
+
+        assert_eq!(state.restart_history.len(), MAX_RESTART_HISTORY_LEN);
+        assert_eq!(state.restart_history[0].time, time(3));
+        assert_eq!(
+            state.restart_history.last().unwrap().time,
+            time(MAX_RESTART_HISTORY_LEN + 2)
+        );
+        assert_eq!(
+            state.last_restart_time,
+            Some(time(MAX_RESTART_HISTORY_LEN + 2))
+        );
+    }
+
+    #[test]
+    fn recovery_is_set_on_matching_restart() {
+        let mut state = ServerState::default();
+        state.record_restart(restart(0));
+        state.record_restart(restart(1));
+
+        state.set_restart_recovery(time(0), 42);
+        state.set_restart_recovery(time(5), 7);
+
+        assert_eq!(state.restart_history[0].recovery_seconds, Some(42));
+        assert_eq!(state.restart_history[1].recovery_seconds, None);
+    }
+
+    #[test]
+    fn missing_state_file_gives_empty_state() {
+        let data_dir = tempfile::tempdir().unwrap();
+
+        let store = Store::load(&data_dir.path().join("data")).unwrap();
+
+        assert!(store.read().unwrap().servers.is_empty());
+        assert_eq!(store.server("main").unwrap().last_restart_time, None);
+    }
+
+    #[test]
+    fn saved_state_is_loaded_back() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let data_path = data_dir.path().join("data");
+
+        Store::load(&data_path)
+            .unwrap()
+            .update(|state| {
+                let server_state = state.server_mut("main");
+                server_state.record_restart(restart(0));
+                server_state.set_restart_recovery(time(0), 42);
+            })
+            .unwrap();
+
+        let store = Store::load(&data_path).unwrap();
+        let server_state = store.server("main").unwrap();
+        assert_eq!(server_state.last_restart_time, Some(time(0)));
+        assert_eq!(server_state.restart_history.len(), 1);
+        assert_eq!(
+            server_state.restart_history[0].trigger,
+            RestartTrigger::Manual
+        );
+        assert_eq!(server_state.restart_history[0].recovery_seconds, Some(42));
+        assert!(store.server("hardcore").unwrap().restart_history.is_empty());
+    }
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        let data_dir = tempfile::tempdir().unwrap();
+        // Separate stores of the same file, as the web server and a CLI command have
+        let stores: Vec<_> = (0..4)
+            .map(|_| Store::load(data_dir.path()).unwrap())
+            .collect();
+
+        std::thread::scope(|scope| {
+            for (i, store) in stores.iter().enumerate() {
+                scope.spawn(move || {
+                    for minute in 0..10 {
+                        store
+                            .update(|state| {
+                                state
+                                    .server_mut("main")
+                                    .record_restart(restart(i * 10 + minute))
+                            })
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let server_state = stores[0].server("main").unwrap();
+        assert_eq!(server_state.restart_history.len(), 40);
+    }
+
+    #[test]
+    fn unreadable_state_file_is_reported() {
+        let data_dir = tempfile::tempdir().unwrap();
+        fs::write(data_dir.path().join(STATE_FILE_NAME), "{").unwrap();
+
+        assert!(Store::load(data_dir.path()).is_err());
+    }
+
+    #[test]
+    fn matching_checksum_passes_verification() {
+        let mut state = ServerState::default();
+        state.record_backup_checksum("backup.tar.gz", "abc".to_string());
+
+        state.record_backup_verification("backup.tar.gz", Ok("abc".to_string()));
+
+        let record = &state.backups["backup.tar.gz"];
+        assert!(record.verified);
+        assert!(record.verified_at.is_some());
+        assert_eq!(record.verification_error, None);
+    }
+
+    #[test]
+    fn checksum_mismatch_fails_verification() {
+        let mut state = ServerState::default();
+        state.record_backup_checksum("backup.tar.gz", "abc".to_string());
+
+        state.record_backup_verification("backup.tar.gz", Ok("def".to_string()));
+
+        let record = &state.backups["backup.tar.gz"];
+        assert!(!record.verified);
+        assert!(record.verification_error.is_some());
+        assert_eq!(record.sha256.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn checksum_is_recorded_on_first_verification() {
+        let mut state = ServerState::default();
+
+        state.record_backup_verification("backup.tar.gz", Ok("abc".to_string()));
+
+        let record = &state.backups["backup.tar.gz"];
+        assert!(record.verified);
+        assert_eq!(record.sha256.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn unreadable_backup_fails_verification() {
+        let mut state = ServerState::default();
+        state.record_backup_verification("backup.tar.gz", Ok("abc".to_string()));
+
+        state.record_backup_verification(
+            "backup.tar.gz",
+            Err(StoreError("corrupt deflate stream".to_string())),
+        );
+
+        let record = &state.backups["backup.tar.gz"];
+        assert!(!record.verified);
+        assert!(record
+            .verification_error
+            .as_deref()
+            .unwrap()
+            .contains("corrupt deflate stream"));
+        assert_eq!(record.sha256.as_deref(), Some("abc"));
+    }
+}
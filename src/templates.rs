@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDateTime;
+use log::{debug, info};
+use minijinja::{Environment, ErrorKind};
+use serde::Serialize;
+
+use crate::{error::Result, TEMPLATES_DIR};
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const DEFAULT_MAIN_TEMPLATE: &str = "main.html";
+
+pub struct Templates {
+    env: Environment<'static>,
+    main_template: String,
+}
+
+impl Templates {
+    /// Creates template environment with templates embedded in the binary. When `main_template_path`
+    /// exists, it overrides embedded main template and any template placed in the same directory
+    /// overrides its embedded counterpart. Templates ending with `.html` are auto-escaped.
+    pub fn new(main_template_path: &Path) -> Result<Self> {
+        let (override_dir, main_template) = if main_template_path.is_file() {
+            info!(
+                "Using page template override {}",
+                main_template_path.display()
+            );
+
+            (
+                main_template_path.parent().map(Path::to_path_buf),
+                main_template_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| DEFAULT_MAIN_TEMPLATE.to_string()),
+            )
+        } else {
+            info!(
+                "Page template {} not found, using embedded templates",
+                main_template_path.display()
+            );
+
+            (None, DEFAULT_MAIN_TEMPLATE.to_string())
+        };
+
+        let mut env = Environment::new();
+        env.set_loader(move |name| load_template(override_dir.as_deref(), name));
+        env.add_filter("datetime", datetime);
+
+        // Fail early when main template is missing or broken
+        env.get_template(&main_template)?;
+
+        Ok(Self { env, main_template })
+    }
+
+    pub fn render_main<S: Serialize>(&self, ctx: S) -> Result<String> {
+        self.render(&self.main_template, ctx)
+    }
+
+    pub fn render<S: Serialize>(&self, name: &str, ctx: S) -> Result<String> {
+        Ok(self.env.get_template(name)?.render(ctx)?)
+    }
+}
+
+/// Returns directory watched for template overrides
+pub fn override_dir(main_template_path: &Path) -> Option<PathBuf> {
+    main_template_path
+        .parent()
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir.to_path_buf()
+            }
+        })
+        .filter(|dir| dir.is_dir())
+}
+
+fn load_template(
+    override_dir: Option<&Path>,
+    name: &str,
+) -> std::result::Result<Option<String>, minijinja::Error> {
+    if let Some(override_dir) = override_dir {
+        let path = override_dir.join(name);
+
+        if path.is_file() {
+            debug!("Loading template {} from {}", name, path.display());
+
+            return fs::read_to_string(&path).map(Some).map_err(|e| {
+                minijinja::Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("could not read template {}", path.display()),
+                )
+                .with_source(e)
+            });
+        }
+    }
+
+    Ok(TEMPLATES_DIR
+        .get_file(name)
+        .and_then(|file| file.contents_utf8())
+        .map(str::to_string))
+}
+
+/// Formats serialized `NaiveDateTime` values, leaves anything else untouched
+fn datetime(value: String) -> String {
+    value
+        .parse::<NaiveDateTime>()
+        .map(|dt| dt.format(DATETIME_FORMAT).to_string())
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use minijinja::context;
+
+    use super::*;
+
+    fn embedded_source(name: &str) -> &'static str {
+        TEMPLATES_DIR
+            .get_file(name)
+            .and_then(|file| file.contents_utf8())
+            .unwrap()
+    }
+
+    fn source(templates: &Templates, name: &str) -> String {
+        templates
+            .env
+            .get_template(name)
+            .unwrap()
+            .source()
+            .to_string()
+    }
+
+    #[test]
+    fn embedded_templates_are_used_without_override() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let templates = Templates::new(&dir.path().join("missing").join("main.html")).unwrap();
+
+        assert_eq!(templates.main_template, DEFAULT_MAIN_TEMPLATE);
+        assert_eq!(
+            source(&templates, "main.html"),
+            embedded_source("main.html")
+        );
+    }
+
+    #[test]
+    fn main_template_override_falls_back_to_embedded_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let main_template_path = dir.path().join("custom.html");
+        fs::write(&main_template_path, "Hello {{ name }}").unwrap();
+
+        let templates = Templates::new(&main_template_path).unwrap();
+
+        assert_eq!(
+            templates.render_main(context! { name => "<b>" }).unwrap(),
+            "Hello &lt;b&gt;"
+        );
+        assert_eq!(
+            source(&templates, "layout.html"),
+            embedded_source("layout.html")
+        );
+    }
+
+    #[test]
+    fn templates_next_to_main_template_override_embedded_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let main_template_path = dir.path().join("main.html");
+        fs::write(
+            &main_template_path,
+            r#"{% include "partials/status.html" %}"#,
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("partials")).unwrap();
+        fs::write(dir.path().join("partials/status.html"), "Custom status").unwrap();
+
+        let templates = Templates::new(&main_template_path).unwrap();
+
+        assert_eq!(templates.render_main(()).unwrap(), "Custom status");
+    }
+
+    #[test]
+    fn broken_main_template_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let main_template_path = dir.path().join("main.html");
+        fs::write(&main_template_path, "{% block content %}").unwrap();
+
+        assert!(Templates::new(&main_template_path).is_err());
+    }
+}
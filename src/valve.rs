@@ -1,14 +1,18 @@
 use crate::error::Result;
 use a2s::A2SClient;
-use log::{debug, info};
-use std::net::SocketAddr;
+use log::debug;
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Player {
     pub name: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ValveInformation {
     pub server_name: String,
     pub version: String,
@@ -56,3 +60,24 @@ pub async fn retrieve_valve_info(
         vec![],
     ))
 }
+
+/// Polls the server with A2S info queries until it answers or `timeout` passes.
+/// Returns time elapsed until the first successful answer.
+pub async fn wait_for_server(
+    a2s_client: &A2SClient,
+    addr: &SocketAddr,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Option<Duration> {
+    let start = Instant::now();
+
+    while start.elapsed() < timeout {
+        tokio::time::sleep(poll_interval).await;
+
+        if a2s_client.info(addr).await.is_ok() {
+            return Some(start.elapsed());
+        }
+    }
+
+    None
+}
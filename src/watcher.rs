@@ -0,0 +1,181 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use log::{debug, error, info, warn};
+use notify::{recommended_watcher, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::{
+    config::Config,
+    error::Result,
+    templates::{self, Templates},
+    SharedState,
+};
+
+/// Editors usually emit several events for a single save, wait for them to settle before reloading
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/// Starts watching `path` for changes accepted by `filter`. Returned watcher has to be kept alive
+/// for as long as change notifications are needed.
+fn watch<F>(
+    path: &Path,
+    mode: RecursiveMode,
+    filter: F,
+) -> Result<(RecommendedWatcher, UnboundedReceiver<()>)>
+where
+    F: Fn(&Event) -> bool + Send + 'static,
+{
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let mut watcher = recommended_watcher(move |event: notify::Result<Event>| match event {
+        Ok(event) if !event.kind.is_access() && filter(&event) => {
+            let _ = tx.send(());
+        }
+        Ok(_) => {}
+        Err(e) => error!("File watcher error: {}", e),
+    })?;
+    watcher.watch(path, mode)?;
+
+    Ok((watcher, rx))
+}
+
+/// Waits for the next change, swallowing any burst of events following it
+async fn next_change(rx: &mut UnboundedReceiver<()>) -> Option<()> {
+    rx.recv().await?;
+    tokio::time::sleep(DEBOUNCE_DELAY).await;
+    while rx.try_recv().is_ok() {}
+
+    Some(())
+}
+
+/// Reloads page templates whenever anything in the template override directory changes
+pub fn spawn_templates_watcher(state: SharedState, template_path: &Path) {
+    let Some(override_dir) = templates::override_dir(template_path) else {
+        debug!("No template override directory, template hot reload disabled");
+
+        return;
+    };
+
+    let (watcher, mut rx) = match watch(&override_dir, RecursiveMode::Recursive, |_| true) {
+        Ok(watch) => watch,
+        Err(e) => {
+            error!(
+                "Failed watching template directory {}: {}",
+                override_dir.display(),
+                e
+            );
+
+            return;
+        }
+    };
+    info!(
+        "Watching template directory {} for changes",
+        override_dir.display()
+    );
+
+    let template_path = template_path.to_path_buf();
+    tokio::spawn(async move {
+        // Keep watcher alive for the lifetime of the task
+        let _watcher = watcher;
+
+        while next_change(&mut rx).await.is_some() {
+            match Templates::new(&template_path) {
+                Ok(templates) => {
+                    info!("Page templates reloaded");
+
+                    state.write().await.templates = templates.into();
+                }
+                Err(e) => error!("Failed reloading page templates, keeping previous: {}", e),
+            }
+        }
+    });
+}
+
+/// Re-reads configuration whenever config file changes. Valid configuration replaces the current one,
+/// rejected one is reported and the current configuration stays in place.
+pub fn spawn_config_watcher(state: SharedState, config_path: &Path) {
+    let config_path = config_path.to_path_buf();
+    let config_dir = config_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let config_file_name = config_path.file_name().map(|name| name.to_os_string());
+
+    // Watch the whole directory, editors often replace files instead of writing them in place
+    let (watcher, mut rx) = match watch(&config_dir, RecursiveMode::NonRecursive, move |event| {
+        event
+            .paths
+            .iter()
+            .any(|path| path.file_name().map(|name| name.to_os_string()) == config_file_name)
+    }) {
+        Ok(watch) => watch,
+        Err(e) => {
+            error!(
+                "Failed watching config file {}: {}",
+                config_path.display(),
+                e
+            );
+
+            return;
+        }
+    };
+    info!("Watching config file {} for changes", config_path.display());
+
+    tokio::spawn(async move {
+        // Keep watcher alive for the lifetime of the task
+        let _watcher = watcher;
+
+        while next_change(&mut rx).await.is_some() {
+            match Config::new(&config_path) {
+                Ok(config) => {
+                    let mut state = state.write().await;
+
+                    for property in state.config.restart_required_changes(&config) {
+                        warn!(
+                            "Config property {} changed, restart valman to apply it",
+                            property
+                        );
+                    }
+
+                    state.config = Arc::new(config);
+                    state.config_reload_error = None;
+
+                    info!("Config reloaded");
+                }
+                Err(e) => {
+                    error!("Config reload rejected, keeping previous config: {}", e);
+
+                    state.write().await.config_reload_error = Some(e.to_string());
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn changed_template_is_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let main_template_path = dir.path().join("main.html");
+        fs::write(&main_template_path, "Before").unwrap();
+        let (_watcher, mut rx) = watch(dir.path(), RecursiveMode::Recursive, |_| true).unwrap();
+
+        fs::write(&main_template_path, "After").unwrap();
+        tokio::time::timeout(Duration::from_secs(10), next_change(&mut rx))
+            .await
+            .expect("change notification")
+            .unwrap();
+
+        let templates = Templates::new(&main_template_path).unwrap();
+        assert_eq!(templates.render_main(()).unwrap(), "After");
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use flate2::{write::GzEncoder, Compression};
+use humansize::DECIMAL;
+use log::debug;
+use serde::Serialize;
+use tar::Builder;
+
+use crate::error::{Result, ValmanError::World as WorldError};
+
+const WORLDS_DIR_NAME: &str = "worlds_local";
+/// Saves directory inside `valheim_backups_destination_path`, as in valheim-docker volume layout
+pub(crate) const SAVES_DIR_NAME: &str = "saves";
+/// World metadata and world data files, Valheim keeps previous versions with `.old` suffix
+const WORLD_FILE_EXTENSIONS: [&str; 4] = ["fwl", "db", "fwl.old", "db.old"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldFile {
+    pub name: String,
+    pub modification_time: NaiveDateTime,
+    pub hr_size: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct World {
+    pub name: String,
+    pub files: Vec<WorldFile>,
+    /// Whether both metadata (`.fwl`) and data (`.db`) files are present
+    pub complete: bool,
+    pub modification_time: NaiveDateTime,
+}
+
+/// Locates `worlds_local` directory in the save directory, which is either `valheim_backups_destination_path`
+/// itself or its `saves` subdirectory
+pub fn worlds_path(destination_path: &Path) -> PathBuf {
+    let worlds_path = destination_path.join(WORLDS_DIR_NAME);
+    if worlds_path.is_dir() {
+        return worlds_path;
+    }
+
+    let saves_worlds_path = destination_path.join(SAVES_DIR_NAME).join(WORLDS_DIR_NAME);
+    if saves_worlds_path.is_dir() {
+        saves_worlds_path
+    } else {
+        worlds_path
+    }
+}
+
+/// Splits world file name into world name and extension, `None` for files not belonging to a world
+fn split_world_file_name(file_name: &str) -> Option<(&str, &str)> {
+    WORLD_FILE_EXTENSIONS.iter().find_map(|extension| {
+        let name = file_name.strip_suffix(extension)?.strip_suffix('.')?;
+
+        (!name.is_empty()).then_some((name, *extension))
+    })
+}
+
+/// Names of worlds whose files are among `paths`, sorted and without duplicates
+pub fn world_names<'a>(paths: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let names: BTreeSet<_> = paths
+        .filter_map(|path| {
+            let file_name = path.rsplit('/').next()?;
+
+            split_world_file_name(file_name).map(|(name, _)| name.to_string())
+        })
+        .collect();
+
+    names.into_iter().collect()
+}
+
+/// World names end up in file names and container environment, only plain names are accepted
+pub fn validate_world_name(name: &str) -> Result<()> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(WorldError(format!("Invalid world name {}", name)))
+    }
+}
+
+/// Lists worlds in `worlds_path` with their files, most recently modified first
+pub fn list_worlds(worlds_path: &Path) -> Result<Vec<World>> {
+    if !worlds_path.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut worlds: BTreeMap<String, Vec<WorldFile>> = BTreeMap::new();
+    for entry in fs::read_dir(worlds_path)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some((world_name, _)) = split_world_file_name(&file_name) else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        let modification_time: DateTime<Local> = metadata.modified()?.into();
+
+        worlds
+            .entry(world_name.to_string())
+            .or_default()
+            .push(WorldFile {
+                name: file_name,
+                modification_time: modification_time.naive_local(),
+                hr_size: humansize::format_size(metadata.len(), DECIMAL),
+            });
+    }
+
+    let mut worlds: Vec<_> = worlds
+        .into_iter()
+        .map(|(name, mut files)| {
+            files.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+            let has_file = |extension: &str| {
+                files
+                    .iter()
+                    .any(|file| file.name == format!("{}.{}", name, extension))
+            };
+
+            World {
+                complete: has_file("fwl") && has_file("db"),
+                modification_time: files
+                    .iter()
+                    .map(|file| file.modification_time)
+                    .max()
+                    .unwrap_or_default(),
+                name,
+                files,
+            }
+        })
+        .collect();
+    worlds.sort_unstable_by_key(|world| std::cmp::Reverse(world.modification_time));
+
+    Ok(worlds)
+}
+
+/// Existing files of the world as file name and path pairs, fails when there are none
+pub fn world_files(worlds_path: &Path, world_name: &str) -> Result<Vec<(String, PathBuf)>> {
+    validate_world_name(world_name)?;
+
+    let files: Vec<_> = WORLD_FILE_EXTENSIONS
+        .iter()
+        .map(|extension| format!("{}.{}", world_name, extension))
+        .map(|file_name| {
+            let path = worlds_path.join(&file_name);
+
+            (file_name, path)
+        })
+        .filter(|(_, path)| path.is_file())
+        .collect();
+    if files.is_empty() {
+        return Err(WorldError(format!("World {} not found", world_name)));
+    }
+
+    Ok(files)
+}
+
+/// Archives all files of the world into a tar.gz written to `writer`
+pub fn archive_world<W: Write>(worlds_path: &Path, world_name: &str, writer: W) -> Result<()> {
+    let files = world_files(worlds_path, world_name)?;
+
+    debug!(
+        "Archiving world {} from {}",
+        world_name,
+        worlds_path.display()
+    );
+
+    let mut archive = Builder::new(GzEncoder::new(writer, Compression::default()));
+    for (file_name, path) in files {
+        archive.append_path_with_name(&path, &file_name)?;
+    }
+    archive.into_inner()?.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Checks that uploaded file is `.fwl` or `.db` file of a world not among `uploaded` files already,
+/// returns the world name
+pub fn validate_upload_file_name<'a>(file_name: &'a str, uploaded: &[String]) -> Result<&'a str> {
+    let name = match split_world_file_name(file_name) {
+        Some((name, "fwl" | "db")) => name,
+        _ => {
+            return Err(WorldError(format!(
+                "Unexpected file {}, only .fwl and .db world files are accepted",
+                file_name
+            )))
+        }
+    };
+    validate_world_name(name)?;
+    if uploaded.iter().any(|uploaded| uploaded == file_name) {
+        return Err(WorldError(format!(
+            "File {} is uploaded more than once",
+            file_name
+        )));
+    }
+
+    Ok(name)
+}
+
+/// Checks that uploaded files are `<world>.fwl` and `<world>.db` of the same world, returns its name
+pub fn validate_upload(file_names: &[String]) -> Result<String> {
+    let mut world_name = None;
+    for (i, file_name) in file_names.iter().enumerate() {
+        let name = validate_upload_file_name(file_name, &file_names[..i])?;
+
+        if *world_name.get_or_insert(name) != name {
+            return Err(WorldError(
+                "All uploaded files have to belong to the same world".to_string(),
+            ));
+        }
+    }
+    let Some(world_name) = world_name.map(str::to_string) else {
+        return Err(WorldError("No world files uploaded".to_string()));
+    };
+    let uploaded = |extension: &str| {
+        file_names
+            .iter()
+            .any(|file_name| *file_name == format!("{}.{}", world_name, extension))
+    };
+    if !(uploaded("fwl") && uploaded("db")) {
+        return Err(WorldError(format!(
+            "Both {0}.fwl and {0}.db files are required",
+            world_name
+        )));
+    }
+
+    Ok(world_name)
+}
+
+/// Files of the world the server runs are in use and rewritten by the server on save, so uploads
+/// never replace them
+pub fn ensure_not_active(world_name: &str, active_world: Option<&str>) -> Result<()> {
+    if active_world == Some(world_name) {
+        return Err(WorldError(format!(
+            "World {} is active on the server, activate another world before replacing it",
+            world_name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Hidden file an uploaded world file is written to before the whole upload is validated
+pub fn upload_path(worlds_path: &Path, file_name: &str) -> PathBuf {
+    worlds_path.join(format!(".{}.upload", file_name))
+}
+
+/// Moves world files uploaded to their [`upload_path`] in place, existing files of the world are
+/// replaced. Files are moved only once all of them are uploaded, so a failed upload never leaves
+/// a half replaced world behind.
+pub fn import_world(worlds_path: &Path, file_names: &[String]) -> Result<String> {
+    let world_name = validate_upload(file_names)?;
+
+    debug!(
+        "Importing world {} to {}",
+        world_name,
+        worlds_path.display()
+    );
+
+    for file_name in file_names {
+        fs::rename(
+            upload_path(worlds_path, file_name),
+            worlds_path.join(file_name),
+        )?;
+    }
+
+    Ok(world_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(file_names: &[&str]) -> Vec<String> {
+        file_names
+            .iter()
+            .map(|file_name| file_name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn world_files_are_accepted() {
+        assert_eq!(
+            validate_upload(&upload(&["Midgard.db", "Midgard.fwl"])).unwrap(),
+            "Midgard"
+        );
+    }
+
+    #[test]
+    fn both_world_files_are_required() {
+        assert!(validate_upload(&upload(&[])).is_err());
+        assert!(validate_upload(&upload(&["Midgard.fwl"])).is_err());
+        assert!(validate_upload(&upload(&["Midgard.db", "Midgard.db.old"])).is_err());
+    }
+
+    #[test]
+    fn files_of_different_worlds_are_rejected() {
+        assert!(validate_upload(&upload(&["Midgard.fwl", "Asgard.db"])).is_err());
+    }
+
+    #[test]
+    fn duplicate_files_are_rejected() {
+        assert!(validate_upload(&upload(&["Midgard.fwl", "Midgard.db", "Midgard.db"])).is_err());
+        assert!(validate_upload_file_name("Midgard.db", &upload(&["Midgard.db"])).is_err());
+        assert_eq!(
+            validate_upload_file_name("Midgard.db", &upload(&["Midgard.fwl"])).unwrap(),
+            "Midgard"
+        );
+    }
+
+    #[test]
+    fn active_world_is_not_replaced() {
+        assert!(ensure_not_active("Midgard", Some("Midgard")).is_err());
+        assert!(ensure_not_active("Midgard", Some("Asgard")).is_ok());
+        assert!(ensure_not_active("Midgard", None).is_ok());
+    }
+
+    #[test]
+    fn invalid_file_names_are_rejected() {
+        assert!(validate_upload(&upload(&["Midgard.fwl", "Midgard.txt"])).is_err());
+        assert!(validate_upload(&upload(&["../Midgard.fwl", "../Midgard.db"])).is_err());
+        assert!(validate_upload(&upload(&["Mid gard.fwl", "Mid gard.db"])).is_err());
+        assert!(validate_upload(&upload(&[".fwl", ".db"])).is_err());
+    }
+}